@@ -0,0 +1,234 @@
+use super::format::{DATETIME_NUM_FORMAT, DATE_NUM_FORMAT};
+use crate::model::*;
+use std::collections::BTreeMap;
+use xlsxwriter::worksheet::WorksheetCol;
+
+const DEFAULT_FONT_SIZE: f64 = 11.;
+const DEFAULT_MAX_WIDTH: f64 = 80.;
+const CELL_PADDING: f64 = 1.5;
+
+#[derive(Debug, Default)]
+pub struct ColumnWidths {
+    active: Option<AutoFitDef>,
+    widths: BTreeMap<WorksheetCol, f64>,
+    // Widths set by sources themselves, such as VCF columns
+    source_widths: BTreeMap<WorksheetCol, f64>,
+}
+
+impl ColumnWidths {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_active(&mut self, options: Option<AutoFitDef>) {
+        self.active = options;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.is_some()
+    }
+
+    pub fn record_text(
+        &mut self,
+        column: WorksheetCol,
+        text: &str,
+        format: Option<&FormatDef>,
+        header: bool,
+    ) {
+        let options = match self.active.as_ref() {
+            Some(x) if x.header || !header => x,
+            _ => return,
+        };
+        let font_size = format
+            .and_then(|x| x.font_size)
+            .map(|x| x as f64)
+            .unwrap_or(DEFAULT_FONT_SIZE);
+        let mut width = text_width(text) * font_size / DEFAULT_FONT_SIZE + CELL_PADDING;
+        if header {
+            // keep room for the autofilter button
+            width += 2.;
+        }
+        let width = width
+            .min(options.max_width.unwrap_or(DEFAULT_MAX_WIDTH))
+            .max(options.min_width.unwrap_or(0.));
+        let current = self.widths.entry(column).or_insert(0.);
+        *current = current.max(width);
+    }
+
+    pub fn record_value(
+        &mut self,
+        column: WorksheetCol,
+        value: &CellValue,
+        cell_type: CellType,
+        format: Option<&FormatDef>,
+        header: bool,
+    ) {
+        if self.active.is_none() {
+            return;
+        }
+        let num_format = format.and_then(|x| x.num_format.as_deref());
+        let text = rendered_text(value, cell_type, num_format);
+        self.record_text(column, &text, format, header);
+    }
+
+    pub fn get(&self, column: WorksheetCol) -> Option<f64> {
+        self.widths.get(&column).copied()
+    }
+
+    pub fn widths(&self) -> impl Iterator<Item = (WorksheetCol, f64)> + '_ {
+        self.widths.iter().map(|(x, y)| (*x, *y))
+    }
+
+    pub fn record_source_width(&mut self, column: WorksheetCol, width: f64) {
+        self.source_widths.insert(column, width);
+    }
+
+    pub fn source_width(&self, column: WorksheetCol) -> Option<f64> {
+        self.source_widths.get(&column).copied()
+    }
+}
+
+pub fn text_width(text: &str) -> f64 {
+    text.lines()
+        .map(|line| {
+            line.chars()
+                .map(|c| {
+                    if c.is_ascii() {
+                        if c.is_ascii_uppercase() || c == 'm' || c == 'w' {
+                            1.2
+                        } else {
+                            1.
+                        }
+                    } else if c as u32 >= 0x1100 {
+                        2.
+                    } else {
+                        1.
+                    }
+                })
+                .sum::<f64>()
+        })
+        .fold(0., f64::max)
+}
+
+fn format_number(value: f64, num_format: Option<&str>) -> String {
+    let num_format = match num_format {
+        Some(x) if x != "General" && x != "@" => x,
+        _ => {
+            let text = value.to_string();
+            return if text.len() > 11 {
+                format!("{:.5e}", value)
+            } else {
+                text
+            };
+        }
+    };
+    let decimals = num_format
+        .split_once('.')
+        .map(|(_, x)| x.chars().take_while(|y| *y == '0' || *y == '#').count())
+        .unwrap_or(0);
+    let percent = num_format.contains('%');
+    let mut text = format!(
+        "{:.*}",
+        decimals,
+        if percent { value * 100. } else { value }
+    );
+    if num_format.contains(',') {
+        let (integer, fraction) = match text.split_once('.') {
+            Some((x, y)) => (x.to_string(), format!(".{}", y)),
+            None => (text.clone(), String::new()),
+        };
+        let digits = integer.trim_start_matches('-');
+        let mut grouped = String::new();
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(c);
+        }
+        let sign = if integer.starts_with('-') { "-" } else { "" };
+        text = format!("{}{}{}", sign, grouped, fraction);
+    }
+    if percent {
+        text.push('%');
+    }
+    text
+}
+
+pub fn rendered_text(value: &CellValue, cell_type: CellType, num_format: Option<&str>) -> String {
+    match value {
+        CellValue::Null => String::new(),
+        CellValue::Boolean(x) => if *x { "TRUE" } else { "FALSE" }.to_string(),
+        CellValue::String(x) | CellValue::Url(x) => x.to_string(),
+        CellValue::Formula(_) => String::new(),
+        CellValue::Rich(x) => x.rich.iter().map(|y| y.text.as_str()).collect(),
+        CellValue::Percent(x) => format_number(*x, num_format.or(Some("0.0%"))),
+        CellValue::Number(x) => match cell_type {
+            CellType::Datetime => num_format
+                .unwrap_or(if x.fract() != 0. {
+                    DATETIME_NUM_FORMAT
+                } else {
+                    DATE_NUM_FORMAT
+                })
+                .to_string(),
+            CellType::Percent => format_number(*x, num_format.or(Some("0.0%"))),
+            _ => format_number(*x, num_format),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rendered_text() {
+        assert_eq!(
+            rendered_text(&CellValue::Number(1234.5), CellType::Number, None),
+            "1234.5"
+        );
+        assert_eq!(
+            rendered_text(
+                &CellValue::Number(-1234567.891),
+                CellType::Number,
+                Some("#,##0.00")
+            ),
+            "-1,234,567.89"
+        );
+        assert_eq!(
+            rendered_text(&CellValue::Percent(0.1234), CellType::Percent, None),
+            "12.3%"
+        );
+        assert_eq!(
+            rendered_text(&CellValue::Number(44256.), CellType::Datetime, None),
+            "yyyy-mm-dd"
+        );
+        assert_eq!(
+            rendered_text(&CellValue::Number(44256.5), CellType::Datetime, None),
+            "yyyy-mm-dd hh:mm:ss"
+        );
+        assert_eq!(
+            rendered_text(&CellValue::Boolean(false), CellType::Boolean, None),
+            "FALSE"
+        );
+    }
+
+    #[test]
+    fn test_column_widths() {
+        let mut widths = ColumnWidths::new();
+        widths.record_text(0, "ignored while inactive", None, false);
+        assert!(widths.widths.is_empty());
+
+        widths.set_active(Some(AutoFitDef {
+            min_width: Some(5.),
+            max_width: Some(20.),
+            header: false,
+        }));
+        widths.record_text(0, "a very long header that is skipped", None, true);
+        widths.record_text(0, "abc", None, false);
+        widths.record_text(1, "日本語", None, false);
+        widths.record_text(2, &"x".repeat(100), None, false);
+        assert_eq!(widths.widths.get(&0), Some(&5.));
+        assert_eq!(widths.widths.get(&1), Some(&7.5));
+        assert_eq!(widths.widths.get(&2), Some(&20.));
+    }
+}
@@ -1,8 +1,57 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
 use xlsxwriter::worksheet::{Worksheet, WorksheetCol, WorksheetRow};
 use xlsxwriter::Format;
 
+use super::format::create_format;
 use crate::model::*;
 
+static DATE_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^(\d{4})[-/](\d{1,2})[-/](\d{1,2})(?:[ T](\d{1,2}):(\d{2})(?::(\d{2}(?:\.\d+)?))?)?$",
+    )
+    .unwrap()
+});
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+pub fn parse_date(data: &str) -> Option<f64> {
+    let captures = DATE_PATTERN.captures(data.trim())?;
+    let number = |i: usize| -> f64 {
+        captures
+            .get(i)
+            .map(|x| x.as_str().parse().unwrap_or(0.))
+            .unwrap_or(0.)
+    };
+    let (year, month, day) = (number(1) as i64, number(2) as i64, number(3) as i64);
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => return None,
+    };
+    let (hour, minute, second) = (number(4), number(5), number(6));
+    if year < 1900
+        || day < 1
+        || day > days_in_month
+        || hour >= 24.
+        || minute >= 60.
+        || second >= 60.
+    {
+        return None;
+    }
+    let days = days_from_civil(year, month, day) - days_from_civil(1899, 12, 30);
+    Some(days as f64 + (hour * 3600. + minute * 60. + second) / 86400.)
+}
+
 pub fn parse_cell(data: &str, cell_type: CellType) -> anyhow::Result<CellValue> {
     if data.is_empty() {
         return Ok(CellValue::Null);
@@ -22,13 +71,20 @@ pub fn parse_cell(data: &str, cell_type: CellType) -> anyhow::Result<CellValue>
             }
         }
         CellType::Percent => {
-            let num = data.trim().parse::<f64>();
+            let num = data.trim().trim_end_matches('%').trim_end().parse::<f64>();
             if let Ok(num) = num {
                 Ok(CellValue::Percent(num / 100.0))
             } else {
                 Err(anyhow::anyhow!("\"{}\" is not number", data))
             }
         }
+        CellType::Datetime => {
+            if let Some(serial) = parse_date(data) {
+                Ok(CellValue::Number(serial))
+            } else {
+                Err(anyhow::anyhow!("\"{}\" is not date", data))
+            }
+        }
         CellType::String => Ok(CellValue::String(data.to_string())),
         CellType::Formula => Ok(CellValue::Formula(data.to_string())),
         CellType::Url => Ok(CellValue::Url(data.to_string())),
@@ -73,10 +129,12 @@ pub fn actual_cell_type(value: &CellValue, cell_type: CellType) -> CellType {
         CellValue::Null => CellType::Null,
         CellValue::String(_) => CellType::String,
         CellValue::Url(_) => CellType::Url,
+        CellValue::Rich(_) => CellType::String,
         CellValue::Percent(_) => CellType::Percent,
         CellValue::Number(_) => match cell_type {
             CellType::Integer => CellType::Integer,
             CellType::Percent => CellType::Percent,
+            CellType::Datetime => CellType::Datetime,
             _ => CellType::Number,
         },
     }
@@ -108,6 +166,39 @@ pub fn write_cell(
         CellValue::Formula(val) => {
             worksheet.write_formula(row, column, val, format)?;
         }
+        CellValue::Rich(val) => write_rich_text(worksheet, row, column, val, format)?,
+    }
+    Ok(())
+}
+
+fn write_rich_text(
+    worksheet: &mut Worksheet,
+    row: WorksheetRow,
+    column: WorksheetCol,
+    rich_text: &RichText,
+    format: Option<&Format>,
+) -> anyhow::Result<()> {
+    let runs = rich_text
+        .rich
+        .iter()
+        .filter(|x| !x.text.is_empty())
+        .map(|x| {
+            Ok((
+                x.text.as_str(),
+                x.format.as_ref().map(create_format).transpose()?,
+            ))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if runs.len() < 2 {
+        let text = runs.first().map(|x| x.0).unwrap_or("");
+        let run_format = runs.first().and_then(|x| x.1.as_ref());
+        worksheet.write_string(row, column, text, format.or(run_format))?;
+        return Ok(());
     }
+    let fragments: Vec<(&str, Option<&Format>)> = runs
+        .iter()
+        .map(|(text, format)| (*text, format.as_ref()))
+        .collect();
+    worksheet.write_rich_string(row, column, &fragments, format)?;
     Ok(())
 }
@@ -0,0 +1,162 @@
+use super::autofit::ColumnWidths;
+use super::FormatManager;
+use crate::model::*;
+use std::collections::BTreeMap;
+use xlsxwriter::worksheet::{RowColOptions, Worksheet, WorksheetCol, LXW_DEF_COL_WIDTH};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColumnSetting {
+    pub width: Option<f64>,
+    pub format: Option<FormatDef>,
+    pub hidden: bool,
+    pub level: u8,
+    pub collapsed: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ColumnSettings {
+    columns: BTreeMap<WorksheetCol, ColumnSetting>,
+}
+
+impl ColumnSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_mut(&mut self, column: WorksheetCol) -> &mut ColumnSetting {
+        self.columns.entry(column).or_default()
+    }
+
+    pub fn add_source(
+        &mut self,
+        source_def: &SheetSourceDef,
+        start_column: WorksheetCol,
+    ) -> anyhow::Result<()> {
+        let data_offset: WorksheetCol = if source_def.source_file_column.is_some() {
+            1
+        } else {
+            0
+        };
+        for (i, column_def) in source_def.columns.iter().flatten().enumerate() {
+            if column_def.width.is_none()
+                && column_def.column_format.is_none()
+                && !column_def.hidden
+                && column_def.outline_level.is_none()
+            {
+                continue;
+            }
+            let setting = self.get_mut(start_column + data_offset + i as WorksheetCol);
+            if column_def.width.is_some() {
+                setting.width = column_def.width;
+            }
+            if column_def.column_format.is_some() {
+                setting.format = column_def.column_format.clone();
+            }
+            setting.hidden |= column_def.hidden;
+            if let Some(level) = column_def.outline_level {
+                if level > 7 {
+                    return Err(anyhow::anyhow!(
+                        "outline-level must be 7 or less: {}",
+                        level
+                    ));
+                }
+                setting.level = level;
+            }
+        }
+        Ok(())
+    }
+
+    /// Width of a column; hiding or grouping a column keeps the width set by auto-fit or its source
+    pub fn width(&self, column: WorksheetCol, auto_fit: &ColumnWidths) -> f64 {
+        self.columns
+            .get(&column)
+            .and_then(|x| x.width)
+            .or_else(|| auto_fit.get(column))
+            .or_else(|| auto_fit.source_width(column))
+            .unwrap_or(LXW_DEF_COL_WIDTH)
+    }
+
+    pub fn apply(
+        &self,
+        worksheet: &mut Worksheet,
+        auto_fit: &ColumnWidths,
+        formats: &FormatManager,
+    ) -> anyhow::Result<()> {
+        for (column, width) in auto_fit.widths() {
+            if !self.columns.contains_key(&column) {
+                worksheet.set_column(column, column, width, None)?;
+            }
+        }
+        for (column, setting) in self.columns.iter() {
+            worksheet.set_column_opt(
+                *column,
+                *column,
+                self.width(*column, auto_fit),
+                setting
+                    .format
+                    .as_ref()
+                    .and_then(|x| formats.get_format(Some(x), CellType::Auto)),
+                &RowColOptions::new(setting.hidden, setting.level, setting.collapsed),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_add_source() -> anyhow::Result<()> {
+        let mut source_def: SheetSourceDef = serde_json::from_value(serde_json::json!({
+            "file": "data.csv",
+            "columns": [
+                {},
+                {"width": 20, "hidden": true},
+                {"outline-level": 1}
+            ]
+        }))?;
+        let mut settings = ColumnSettings::new();
+        settings.add_source(&source_def, 2)?;
+        assert_eq!(
+            settings.columns.keys().copied().collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+        assert_eq!(
+            settings.columns[&3],
+            ColumnSetting {
+                width: Some(20.),
+                format: None,
+                hidden: true,
+                level: 0,
+                collapsed: false,
+            }
+        );
+        assert_eq!(settings.columns[&4].level, 1);
+
+        source_def.columns.as_mut().unwrap()[2].outline_level = Some(8);
+        assert!(ColumnSettings::new().add_source(&source_def, 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_width() -> anyhow::Result<()> {
+        let source_def: SheetSourceDef = serde_json::from_value(serde_json::json!({
+            "file": "data.vcf",
+            "columns": [{"hidden": true}, {"outline-level": 1}, {"width": 20, "hidden": true}]
+        }))?;
+        let mut widths = ColumnWidths::new();
+        for column in 0..4 {
+            widths.record_source_width(column, 12.);
+        }
+        let mut settings = ColumnSettings::new();
+        settings.add_source(&source_def, 0)?;
+        assert_eq!(settings.width(0, &widths), 12.);
+        assert_eq!(settings.width(1, &widths), 12.);
+        assert_eq!(settings.width(2, &widths), 20.);
+        assert_eq!(settings.width(3, &widths), 12.);
+        assert_eq!(settings.width(4, &widths), LXW_DEF_COL_WIDTH);
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+use super::format::color_parse;
+use super::link::find_column;
+use crate::model::*;
+use xlsxwriter::worksheet::{
+    CommentDisplayType, CommentOptions, Worksheet, WorksheetCol, WorksheetRow,
+};
+
+const COMMENT_MAX_LENGTH: usize = 32767;
+
+fn comment_options(comment_def: &CommentDef) -> anyhow::Result<CommentOptions> {
+    let mut options = CommentOptions {
+        visible: match comment_def.visible {
+            Some(true) => CommentDisplayType::Visible,
+            Some(false) => CommentDisplayType::Hidden,
+            None => CommentDisplayType::Default,
+        },
+        author: comment_def.author.clone(),
+        width: comment_def.width,
+        height: comment_def.height,
+        font_name: comment_def.font_name.clone(),
+        font_size: comment_def.font_size.map(|x| x.into()),
+        ..Default::default()
+    };
+    if let Some(color) = comment_def.background_color.as_deref() {
+        options.color = color_parse(color)?;
+    }
+    Ok(options)
+}
+
+fn truncate_comment(text: &str) -> &str {
+    if text.len() <= COMMENT_MAX_LENGTH {
+        return text;
+    }
+    let mut end = COMMENT_MAX_LENGTH;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+pub fn write_comment(
+    worksheet: &mut Worksheet,
+    row: WorksheetRow,
+    column: WorksheetCol,
+    comment: &Comment,
+) -> anyhow::Result<()> {
+    match comment {
+        Comment::Text(text) => worksheet.write_comment(row, column, truncate_comment(text))?,
+        Comment::Def(comment_def) => worksheet.write_comment_opt(
+            row,
+            column,
+            truncate_comment(&comment_def.text),
+            &comment_options(comment_def)?,
+        )?,
+    }
+    Ok(())
+}
+
+pub fn comment_sources(
+    columns: Option<&Vec<SheetSourceColumnDef>>,
+    header: &[String],
+) -> anyhow::Result<Vec<Option<usize>>> {
+    columns
+        .map(|x| {
+            x.iter()
+                .map(|y| {
+                    y.comment_from
+                        .as_deref()
+                        .map(|name| {
+                            find_column(header, name).ok_or_else(|| {
+                                anyhow::anyhow!("Unknown column \"{}\" in comment-from", name)
+                            })
+                        })
+                        .transpose()
+                })
+                .collect()
+        })
+        .unwrap_or_else(|| Ok(Vec::new()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_truncate_comment() {
+        assert_eq!(truncate_comment("short"), "short");
+        let long = "あ".repeat(20000);
+        let truncated = truncate_comment(&long);
+        assert!(truncated.len() <= COMMENT_MAX_LENGTH);
+        assert_eq!(truncated.len() % 3, 0);
+    }
+
+    #[test]
+    fn test_comment_sources() -> anyhow::Result<()> {
+        let header: Vec<String> = ["Gene", "Annotation"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        let columns: Vec<SheetSourceColumnDef> =
+            serde_json::from_str(r#"[{"comment-from": "Annotation"}, {}, {"comment-from": "1"}]"#)?;
+        assert_eq!(
+            comment_sources(Some(&columns), &header)?,
+            vec![Some(1), None, Some(0)]
+        );
+        let columns: Vec<SheetSourceColumnDef> =
+            serde_json::from_str(r#"[{"comment-from": "Transcript"}]"#)?;
+        assert!(comment_sources(Some(&columns), &header).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+use super::overflow::continuation_sheet_name;
+use super::FormatManager;
+use crate::model::*;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use xlsxwriter::worksheet::{Worksheet, WorksheetCol, WorksheetRow};
+
+static CELL_REFERENCE_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\$?([A-Za-z]{1,3})\$?(\d+)$").unwrap());
+
+pub struct ContentsEntry {
+    pub name: String,
+    pub description: Option<String>,
+    pub rows: Option<usize>,
+}
+
+pub fn parse_cell_reference(cell: &str) -> Option<(WorksheetRow, WorksheetCol)> {
+    let captures = CELL_REFERENCE_PATTERN.captures(cell)?;
+    let column = captures[1]
+        .to_ascii_uppercase()
+        .bytes()
+        .fold(0u32, |acc, x| acc * 26 + (x - b'A' + 1) as u32);
+    let row: u32 = captures[2].parse().ok()?;
+    if row == 0 || row > 1048576 || column > 16384 {
+        return None;
+    }
+    Some((row - 1, (column - 1) as WorksheetCol))
+}
+
+pub fn internal_link(sheet: &str, cell: &str) -> String {
+    format!("internal:'{}'!{}", sheet.replace('\'', "''"), cell)
+}
+
+/// Checks whether `sheet` is a name of a continuation sheet, such as `Variants (2)`
+fn is_continuation_sheet(sheet: &str, continued_sheets: &[String]) -> bool {
+    sheet
+        .strip_suffix(')')
+        .and_then(|x| x.rsplit_once(" ("))
+        .and_then(|(_, number)| number.parse::<usize>().ok())
+        .filter(|number| *number >= 2)
+        .map(|number| {
+            continued_sheets
+                .iter()
+                .any(|x| continuation_sheet_name(x, number) == sheet)
+        })
+        .unwrap_or(false)
+}
+
+pub fn link_to_url(
+    link_to: &LinkToDef,
+    sheet_names: &[String],
+    continued_sheets: &[String],
+) -> anyhow::Result<String> {
+    if !sheet_names.contains(&link_to.sheet)
+        && !is_continuation_sheet(&link_to.sheet, continued_sheets)
+    {
+        return Err(anyhow::anyhow!(
+            "Link target sheet \"{}\" does not exist",
+            link_to.sheet
+        ));
+    }
+    let cell = link_to.cell.as_deref().unwrap_or("A1");
+    if parse_cell_reference(cell).is_none() {
+        return Err(anyhow::anyhow!(
+            "\"{}\" is not a valid cell reference",
+            cell
+        ));
+    }
+    Ok(internal_link(&link_to.sheet, cell))
+}
+
+pub fn write_back_link(
+    worksheet: &mut Worksheet,
+    contents_def: &ContentsDef,
+    formats: &FormatManager,
+) -> anyhow::Result<()> {
+    if let Some(cell) = contents_def.back_link_cell.as_deref() {
+        let (row, column) = parse_cell_reference(cell)
+            .ok_or_else(|| anyhow::anyhow!("\"{}\" is not a valid cell reference", cell))?;
+        let format = formats.get_format(None, CellType::Url);
+        worksheet.write_url(
+            row,
+            column,
+            &internal_link(&contents_def.name, "A1"),
+            format,
+        )?;
+        worksheet.write_string(row, column, "Back to contents", format)?;
+    }
+    Ok(())
+}
+
+pub fn write_contents(
+    worksheet: &mut Worksheet,
+    entries: &[ContentsEntry],
+    formats: &FormatManager,
+) -> anyhow::Result<()> {
+    for (i, header) in ["Sheet", "Description", "Rows"].iter().enumerate() {
+        worksheet.write_string(0, i as WorksheetCol, header, None)?;
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        let row = i as WorksheetRow + 1;
+        let format = formats.get_format(None, CellType::Url);
+        worksheet.write_url(row, 0, &internal_link(&entry.name, "A1"), format)?;
+        worksheet.write_string(row, 0, &entry.name, format)?;
+        if let Some(description) = entry.description.as_deref() {
+            worksheet.write_string(row, 1, description, None)?;
+        }
+        if let Some(rows) = entry.rows {
+            worksheet.write_number(
+                row,
+                2,
+                rows as f64,
+                formats.get_format(None, CellType::Integer),
+            )?;
+        }
+    }
+    worksheet.set_column(0, 0, 30., None)?;
+    worksheet.set_column(1, 1, 60., None)?;
+    worksheet.set_column(2, 2, 10., None)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_cell_reference() {
+        assert_eq!(parse_cell_reference("A1"), Some((0, 0)));
+        assert_eq!(parse_cell_reference("$B$3"), Some((2, 1)));
+        assert_eq!(parse_cell_reference("aa10"), Some((9, 26)));
+        assert_eq!(parse_cell_reference("XFD1048576"), Some((1048575, 16383)));
+        assert_eq!(parse_cell_reference("XFE1"), None);
+        assert_eq!(parse_cell_reference("A0"), None);
+        assert_eq!(parse_cell_reference("1A"), None);
+    }
+
+    #[test]
+    fn test_link_to_url() {
+        let sheet_names = vec!["Summary".to_string(), "Bob's variants".to_string()];
+        assert_eq!(
+            link_to_url(
+                &LinkToDef {
+                    sheet: "Bob's variants".to_string(),
+                    cell: Some("B2".to_string()),
+                },
+                &sheet_names,
+                &[]
+            )
+            .unwrap(),
+            "internal:'Bob''s variants'!B2"
+        );
+        assert_eq!(
+            link_to_url(
+                &LinkToDef {
+                    sheet: "Summary".to_string(),
+                    cell: None,
+                },
+                &sheet_names,
+                &[]
+            )
+            .unwrap(),
+            "internal:'Summary'!A1"
+        );
+        assert!(link_to_url(
+            &LinkToDef {
+                sheet: "Missing".to_string(),
+                cell: None,
+            },
+            &sheet_names,
+            &[]
+        )
+        .is_err());
+        assert!(link_to_url(
+            &LinkToDef {
+                sheet: "Summary".to_string(),
+                cell: Some("A1:B2".to_string()),
+            },
+            &sheet_names,
+            &[]
+        )
+        .is_err());
+
+        let continued = vec!["Summary".to_string()];
+        let link = |sheet: &str| LinkToDef {
+            sheet: sheet.to_string(),
+            cell: None,
+        };
+        assert!(link_to_url(&link("Summary (2)"), &sheet_names, &continued).is_ok());
+        assert!(link_to_url(&link("Summary (1)"), &sheet_names, &continued).is_err());
+        assert!(link_to_url(&link("Summary (2)"), &sheet_names, &[]).is_err());
+    }
+}
@@ -1,8 +1,15 @@
+use super::cell::actual_cell_type;
 use crate::model::*;
 use once_cell::sync::Lazy;
 use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
-use xlsxwriter::{format::FormatUnderline, Format};
+use xlsxwriter::{
+    format::{FormatScript, FormatUnderline},
+    Format,
+};
+
+pub const DATE_NUM_FORMAT: &str = "yyyy-mm-dd";
+pub const DATETIME_NUM_FORMAT: &str = "yyyy-mm-dd hh:mm:ss";
 
 pub static EMPTY_FORMAT: FormatDef = FormatDef {
     font_name: None,
@@ -12,6 +19,9 @@ pub static EMPTY_FORMAT: FormatDef = FormatDef {
     num_format: None,
     border: None,
     underline: false,
+    bold: false,
+    italic: false,
+    font_script: None,
 };
 
 pub static PERCENT_FORMAT: Lazy<FormatDef> = Lazy::new(|| FormatDef {
@@ -22,6 +32,9 @@ pub static PERCENT_FORMAT: Lazy<FormatDef> = Lazy::new(|| FormatDef {
     num_format: Some("0.0%".to_string()),
     border: None,
     underline: false,
+    bold: false,
+    italic: false,
+    font_script: None,
 });
 
 pub static URL_FORMAT: Lazy<FormatDef> = Lazy::new(|| FormatDef {
@@ -32,6 +45,9 @@ pub static URL_FORMAT: Lazy<FormatDef> = Lazy::new(|| FormatDef {
     num_format: None,
     border: None,
     underline: true,
+    bold: false,
+    italic: false,
+    font_script: None,
 });
 
 #[derive(Default)]
@@ -41,6 +57,7 @@ pub struct FormatManager {
     float_format: HashMap<FormatDef, Rc<xlsxwriter::Format>>,
     percent_format: HashMap<FormatDef, Rc<xlsxwriter::Format>>,
     date_format: HashMap<FormatDef, Rc<xlsxwriter::Format>>,
+    datetime_format: HashMap<FormatDef, Rc<xlsxwriter::Format>>,
     url_format: HashMap<FormatDef, Rc<xlsxwriter::Format>>,
     general_format: HashMap<FormatDef, Rc<xlsxwriter::Format>>,
 }
@@ -100,9 +117,30 @@ fn create_format_base<'a>(new_format: &mut Format, format_def: &FormatDef) -> an
     if format_def.underline {
         new_format.set_underline(FormatUnderline::Single);
     }
+    if format_def.bold {
+        new_format.set_bold();
+    }
+    if format_def.italic {
+        new_format.set_italic();
+    }
+    match format_def.font_script {
+        Some(FontScript::Superscript) => {
+            new_format.set_font_script(FormatScript::SuperScript);
+        }
+        Some(FontScript::Subscript) => {
+            new_format.set_font_script(FormatScript::SubScript);
+        }
+        None => (),
+    }
     Ok(())
 }
 
+pub fn create_format(format_def: &FormatDef) -> anyhow::Result<Format> {
+    let mut new_format = Format::new();
+    create_format_base(&mut new_format, format_def)?;
+    Ok(new_format)
+}
+
 impl FormatManager {
     pub fn new() -> Self {
         Self::default()
@@ -124,6 +162,8 @@ impl FormatManager {
                 .insert(format_def.clone(), new_format.clone());
             self.date_format
                 .insert(format_def.clone(), new_format.clone());
+            self.datetime_format
+                .insert(format_def.clone(), new_format.clone());
             self.general_format
                 .insert(format_def.clone(), new_format.clone());
             self.url_format
@@ -154,10 +194,16 @@ impl FormatManager {
 
             let mut date_format = Format::new();
             create_format_base(&mut date_format, format_def)?;
-            date_format.set_num_format("@");
+            date_format.set_num_format(DATE_NUM_FORMAT);
             self.date_format
                 .insert(format_def.clone(), Rc::new(date_format));
 
+            let mut datetime_format = Format::new();
+            create_format_base(&mut datetime_format, format_def)?;
+            datetime_format.set_num_format(DATETIME_NUM_FORMAT);
+            self.datetime_format
+                .insert(format_def.clone(), Rc::new(datetime_format));
+
             let mut general_format = Format::new();
             create_format_base(&mut general_format, format_def)?;
             self.general_format
@@ -214,6 +260,23 @@ impl FormatManager {
         }
         .map(|x| x.as_ref())
     }
+
+    // Date values with a time part are shown with the time
+    pub fn get_value_format(
+        &self,
+        format_def: Option<&FormatDef>,
+        value: &CellValue,
+        cell_type: CellType,
+    ) -> Option<&Format> {
+        match (value, actual_cell_type(value, cell_type)) {
+            (CellValue::Number(x), CellType::Datetime) if x.fract() != 0. => self
+                .datetime_format
+                .get(format_def.unwrap_or(&EMPTY_FORMAT))
+                .or_else(|| self.datetime_format.get(&EMPTY_FORMAT))
+                .map(|x| x.as_ref()),
+            (_, actual) => self.get_format(format_def, actual),
+        }
+    }
 }
 
 pub fn color_parse(color: &str) -> anyhow::Result<xlsxwriter::format::FormatColor> {
@@ -257,6 +320,9 @@ pub fn collect_format(workbook_def: &WorkbookDef) -> HashSet<FormatDef> {
                             if let Some(format) = one_column.format.as_ref() {
                                 set.insert(format.clone());
                             }
+                            if let Some(format) = one_column.column_format.as_ref() {
+                                set.insert(format.clone());
+                            }
                         }
                     }
                 }
@@ -0,0 +1,140 @@
+use super::cell::parse_date;
+use super::source_file::{SourceInput, TextSourceReader};
+use crate::model::CellType;
+
+type TypeCheck = (CellType, fn(&str) -> bool);
+
+fn has_leading_zero(data: &str) -> bool {
+    let digits = data.trim_start_matches(['+', '-']);
+    let mut chars = digits.chars();
+    chars.next() == Some('0') && chars.next().map(|x| x.is_ascii_digit()).unwrap_or(false)
+}
+
+fn is_integer(data: &str) -> bool {
+    !has_leading_zero(data) && data.parse::<i64>().is_ok()
+}
+
+fn is_number(data: &str) -> bool {
+    !has_leading_zero(data) && data.parse::<f64>().map(|x| x.is_finite()).unwrap_or(false)
+}
+
+fn is_percent(data: &str) -> bool {
+    data.strip_suffix('%')
+        .map(|x| is_number(x.trim()))
+        .unwrap_or(false)
+}
+
+fn is_boolean(data: &str) -> bool {
+    data.eq_ignore_ascii_case("true") || data.eq_ignore_ascii_case("false")
+}
+
+fn is_url(data: &str) -> bool {
+    data.starts_with("https://") || data.starts_with("http://") || data.starts_with("mailto:")
+}
+
+pub fn infer_cell_type<'a, I: IntoIterator<Item = &'a str>>(values: I) -> Option<CellType> {
+    let candidates: [TypeCheck; 6] = [
+        (CellType::Boolean, is_boolean),
+        (CellType::Integer, is_integer),
+        (CellType::Number, is_number),
+        (CellType::Percent, is_percent),
+        (CellType::Datetime, |x| parse_date(x).is_some()),
+        (CellType::Url, is_url),
+    ];
+    let mut matched = [true; 6];
+    let mut found = false;
+    for value in values.into_iter().filter(|x| !x.is_empty()) {
+        found = true;
+        for (i, (_, check)) in candidates.iter().enumerate() {
+            matched[i] = matched[i] && check(value);
+        }
+    }
+    if !found {
+        return None;
+    }
+    Some(
+        candidates
+            .iter()
+            .zip(matched.iter())
+            .find(|(_, x)| **x)
+            .map(|((cell_type, _), _)| *cell_type)
+            .unwrap_or(CellType::String),
+    )
+}
+
+pub fn sample_column_types(
+    inputs: &[SourceInput],
+    reader: &TextSourceReader,
+    rows: usize,
+) -> anyhow::Result<Vec<Option<CellType>>> {
+    let mut samples: Vec<Vec<String>> = Vec::new();
+    let mut count = 0;
+    'inputs: for input in inputs.iter() {
+        for (k, row) in reader.records(input)?.enumerate() {
+            let row = row?;
+            if k < reader.header_records() {
+                continue;
+            }
+            if count >= rows {
+                break 'inputs;
+            }
+            for (j, cell) in row.iter().enumerate() {
+                if samples.len() <= j {
+                    samples.resize(j + 1, Vec::new());
+                }
+                samples[j].push(cell.to_string());
+            }
+            count += 1;
+        }
+    }
+    Ok(samples
+        .iter()
+        .map(|x| infer_cell_type(x.iter().map(|y| y.as_str())))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_infer_cell_type() {
+        assert_eq!(
+            infer_cell_type(vec!["1", "", "-20"]),
+            Some(CellType::Integer)
+        );
+        assert_eq!(infer_cell_type(vec!["1", "2.5"]), Some(CellType::Number));
+        assert_eq!(
+            infer_cell_type(vec!["123", "00123"]),
+            Some(CellType::String)
+        );
+        assert_eq!(infer_cell_type(vec!["0", "0.5"]), Some(CellType::Number));
+        assert_eq!(
+            infer_cell_type(vec!["12.5%", "3%"]),
+            Some(CellType::Percent)
+        );
+        assert_eq!(
+            infer_cell_type(vec!["TRUE", "false"]),
+            Some(CellType::Boolean)
+        );
+        assert_eq!(
+            infer_cell_type(vec!["2021-03-01", "2021/12/31"]),
+            Some(CellType::Datetime)
+        );
+        assert_eq!(
+            infer_cell_type(vec!["https://example.com", "mailto:a@example.com"]),
+            Some(CellType::Url)
+        );
+        assert_eq!(infer_cell_type(vec!["MARCH1", "1"]), Some(CellType::String));
+        assert_eq!(infer_cell_type(vec!["nan", "inf"]), Some(CellType::String));
+        assert_eq!(infer_cell_type(vec!["", ""]), None);
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("2021-03-01"), Some(44256.));
+        assert_eq!(parse_date("2021-03-01 12:00"), Some(44256.5));
+        assert_eq!(parse_date("2021-02-29"), None);
+        assert_eq!(parse_date("March 1"), None);
+    }
+}
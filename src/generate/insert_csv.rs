@@ -1,50 +1,40 @@
-use super::cell::{actual_cell_type, parse_cell, parse_cell_value, write_cell};
-use super::FormatManager;
+use super::autofit::ColumnWidths;
+use super::cell::{parse_cell, parse_cell_value, write_cell};
+use super::comment::{comment_sources, write_comment};
+use super::infer::sample_column_types;
+use super::link::{column_links, ColumnLink};
+use super::overflow::{row_limit_error, OverflowSheets, XLSX_MAX_ROW};
+use super::source_file::{source_inputs, TextSourceReader};
+use super::{FormatManager, InsertedSource, SourceExtent};
 use crate::model::*;
 use anyhow::Context;
+use regex::Regex;
 use std::collections::HashSet;
-use std::io::BufRead;
 use std::path::Path;
 use xlsxwriter::worksheet::{Worksheet, WorksheetCol, WorksheetRow};
 
-pub fn insert_csv<P: AsRef<Path>>(
-    worksheet: &mut Worksheet,
+#[allow(clippy::too_many_arguments)]
+pub fn insert_csv<'b, P: AsRef<Path>>(
+    worksheet: &mut Worksheet<'b>,
+    overflow: &mut OverflowSheets<'b>,
     source_def: &SheetSourceDef,
     formats: &FormatManager,
     base_path: P,
-) -> anyhow::Result<()> {
-    let reader: Box<dyn BufRead> = if let Some(file) = source_def.file.as_deref() {
-        Box::new(std::io::BufReader::new(
-            autocompress::autodetect_open(base_path.as_ref().join(file))
-                .with_context(|| format!("Cannot open \"{}\"", file))?,
-        ))
-    } else if let Some(data) = source_def.data.as_deref() {
-        Box::new(&data.as_bytes()[..])
-    } else {
+    start_row: WorksheetRow,
+    start_column: WorksheetCol,
+    widths: &mut ColumnWidths,
+    verbose: bool,
+) -> anyhow::Result<InsertedSource> {
+    let inputs = source_inputs(source_def, base_path.as_ref())?;
+    if inputs.is_empty() {
         return Err(anyhow::anyhow!("No data found for CSV/TSV"));
-    };
-
-    let mut csv_reader_builder = csv::ReaderBuilder::new();
-    csv_reader_builder.has_headers(false);
-    csv_reader_builder.flexible(true);
-    if let Some(comment_line_prefix) = source_def.comment_line_prefix.as_deref() {
-        if comment_line_prefix.as_bytes().len() == 1 {
-            csv_reader_builder.comment(Some(comment_line_prefix.as_bytes()[0]));
-        } else {
-            return Err(anyhow::anyhow!("Comment line prefix must be length 1"));
-        }
     }
-    match source_def.suggest_format() {
-        SheetSourceType::CSV => {}
-        SheetSourceType::TSV => {
-            csv_reader_builder
-                .delimiter(b'\t')
-                .quoting(false)
-                .double_quote(false);
-        }
-        _ => unreachable!(),
+
+    let text_reader = TextSourceReader::new(source_def, &inputs)?;
+    if source_def.max_rows.is_some() && source_def.tail.is_some() {
+        return Err(anyhow::anyhow!("max-rows and tail cannot be used together"));
     }
-    let mut csv_reader = csv_reader_builder.from_reader(reader);
+    let header_records = text_reader.header_records();
     let mut header_line = Vec::new();
     let mut filter_column_index: Option<usize> = None;
     let filter_list: HashSet<String> = source_def
@@ -57,144 +47,476 @@ pub fn insert_csv<P: AsRef<Path>>(
         eprintln!("filter_list cannot be enabled when table mode is enabled");
     }
 
+    let source_file_pattern = source_def
+        .source_file_column
+        .as_ref()
+        .and_then(|x| x.pattern.as_deref())
+        .map(Regex::new)
+        .transpose()
+        .context("Invalid source file column pattern")?;
+    let data_offset: usize = if source_def.source_file_column.is_some() {
+        1
+    } else {
+        0
+    };
+
     let mut maximum_col = 0;
-    let mut maximum_row = 0;
-    let mut offset_row = source_def.start_row;
+    let mut offset_row = start_row;
 
-    if let Some(columns) = source_def.columns.as_ref() {
-        if !source_def.has_header && columns.iter().any(|y| y.header_value.is_some()) {
-            for (i, one) in columns.iter().enumerate() {
-                if let Some(value) = one.header_value.as_ref() {
-                    let value = parse_cell_value(value, one.header_type)?;
-                    write_cell(worksheet, offset_row, i as WorksheetCol, &value, None)?;
-                }
-                if let Some(comment) = one.header_comment.as_ref() {
-                    worksheet.write_comment(offset_row, i as WorksheetCol, &comment)?;
-                }
+    if !source_def.has_header
+        && source_def
+            .columns
+            .as_ref()
+            .map(|x| x.iter().any(|y| y.header_value.is_some()))
+            .unwrap_or(false)
+    {
+        write_header_row(
+            worksheet,
+            source_def,
+            &header_line,
+            offset_row,
+            start_column,
+            widths,
+        )?;
+        offset_row += 1;
+    }
+
+    let inferred_types = if let Some(rows) = source_def.infer_types {
+        sample_column_types(&inputs, &text_reader, rows)?
+    } else {
+        Vec::new()
+    };
+
+    let skip_data_rows = if let Some(tail) = source_def.tail {
+        let mut data_rows = 0;
+        for input in inputs.iter() {
+            for row in text_reader.records(input)?.skip(header_records) {
+                row?;
+                data_rows += 1;
             }
-            offset_row += 1;
         }
-    }
+        data_rows - tail.min(data_rows)
+    } else {
+        0
+    };
 
-    for (i, row) in csv_reader.records().enumerate() {
-        maximum_row = i;
-        let row = row?;
-        for (j, cell) in row.iter().enumerate() {
-            maximum_col = maximum_col.max(j);
-
-            let link_prefix: Option<String> = source_def
-                .columns
-                .as_ref()
-                .map(|x| x.get(j))
-                .flatten()
-                .map(|x| x.link_prefix.clone())
-                .flatten();
-
-            if i == 0 && source_def.has_header {
-                if let Some(comment) = source_def
-                    .columns
-                    .as_ref()
-                    .map(|x| x.get(j))
-                    .flatten()
-                    .map(|x| x.header_comment.as_deref())
-                    .flatten()
-                {
-                    worksheet.write_comment(
-                        (i as WorksheetRow) + offset_row,
-                        (j as WorksheetCol) + source_def.start_column,
-                        comment,
+    let mut links: Option<Vec<Option<ColumnLink>>> = None;
+    let mut comment_columns: Vec<Option<usize>> = Vec::new();
+    let mut first_extent: Option<SourceExtent> = None;
+    let mut current_row = offset_row;
+    let mut last_row = offset_row;
+    let mut table_first_row = start_row;
+    let mut data_index: usize = 0;
+    let mut data_rows: usize = 0;
+    let mut leading_header_rows: Vec<Vec<String>> = Vec::new();
+    let mut i: usize = 0;
+    'inputs: for input in inputs.iter() {
+        let source_label = input.label(source_file_pattern.as_ref());
+        for (k, row) in text_reader.records(input)?.enumerate() {
+            let row = row?;
+            if k < header_records {
+                if i > 0 {
+                    if k + 1 == header_records && row.iter().ne(header_line[data_offset..].iter()) {
+                        return Err(anyhow::anyhow!(
+                            "Header of \"{}\" does not match to \"{}\"",
+                            input.name,
+                            inputs[0].name
+                        ));
+                    }
+                    continue;
+                }
+                if k + 1 < header_records {
+                    let values: Vec<String> = row.iter().map(|x| x.to_string()).collect();
+                    write_leading_header_row(
+                        worksheet,
+                        source_def,
+                        formats,
+                        &values,
+                        current_row,
+                        start_column + data_offset as WorksheetCol,
+                        widths,
                     )?;
+                    leading_header_rows.push(values);
+                    current_row += 1;
+                    table_first_row = current_row;
+                    continue;
+                }
+            } else {
+                data_index += 1;
+                if data_index <= skip_data_rows {
+                    continue;
+                }
+                if source_def.max_rows.map(|x| data_index > x).unwrap_or(false) {
+                    break 'inputs;
                 }
+            }
 
-                if source_def
-                    .filter_list
-                    .as_ref()
-                    .map(|x| x.column_header == cell)
-                    .unwrap_or(false)
-                    && !source_def.table
-                {
-                    filter_column_index = Some(j);
+            if current_row >= XLSX_MAX_ROW {
+                match source_def.overflow {
+                    OverflowPolicy::Truncate => {
+                        eprintln!("WARNING: The output of \"{}\" is truncated", input.name);
+                        break 'inputs;
+                    }
+                    OverflowPolicy::Error => return Err(row_limit_error()),
+                    OverflowPolicy::NewSheet => {
+                        let extent = SourceExtent {
+                            first_row: start_row,
+                            first_col: start_column,
+                            last_row,
+                            last_col: start_column + maximum_col as WorksheetCol,
+                        };
+                        let sheet = match overflow.last_mut().filter(|_| first_extent.is_some()) {
+                            Some(x) => x,
+                            None => &mut *worksheet,
+                        };
+                        super::table::setup_table(
+                            sheet,
+                            source_def,
+                            formats,
+                            &header_line,
+                            filter_column_index.map(|x| (x + data_offset) as WorksheetCol),
+                            &SourceExtent {
+                                first_row: table_first_row,
+                                ..extent
+                            },
+                        )?;
+                        first_extent.get_or_insert(extent);
+
+                        let sheet = overflow.add_sheet()?;
+                        current_row = start_row;
+                        for values in leading_header_rows.iter() {
+                            write_leading_header_row(
+                                sheet,
+                                source_def,
+                                formats,
+                                values,
+                                current_row,
+                                start_column + data_offset as WorksheetCol,
+                                widths,
+                            )?;
+                            current_row += 1;
+                        }
+                        table_first_row = current_row;
+                        if source_def.has_header || offset_row > start_row {
+                            write_header_row(
+                                sheet,
+                                source_def,
+                                &header_line,
+                                current_row,
+                                start_column,
+                                widths,
+                            )?;
+                            current_row += 1;
+                        }
+                    }
                 }
+            }
+            let worksheet = match overflow.last_mut().filter(|_| first_extent.is_some()) {
+                Some(x) => x,
+                None => &mut *worksheet,
+            };
+            last_row = current_row;
 
-                header_line.push(cell.to_string());
-            } else if filter_column_index.map(|x| x == j).unwrap_or(false) {
-                if !filter_list.contains(cell) {
-                    worksheet.set_row_opt(
-                        (i as WorksheetRow) + offset_row,
-                        xlsxwriter::worksheet::LXW_DEF_ROW_HEIGHT,
+            if let Some(source_file_column) = source_def.source_file_column.as_ref() {
+                if i == 0 && source_def.has_header {
+                    header_line.push(source_file_column.header.to_string());
+                    worksheet.write_string(
+                        current_row,
+                        start_column,
+                        &source_file_column.header,
                         None,
-                        &xlsxwriter::worksheet::RowColOptions::new(true, 0, false),
                     )?;
+                    widths.record_text(start_column, &source_file_column.header, None, true);
+                } else {
+                    worksheet.write_string(
+                        current_row,
+                        start_column,
+                        &source_label,
+                        formats.get_format(None, CellType::String),
+                    )?;
+                    widths.record_text(start_column, &source_label, None, false);
                 }
             }
+            if filter_column_index
+                .and_then(|x| row.get(x))
+                .map(|x| !filter_list.contains(x))
+                .unwrap_or(false)
+            {
+                worksheet.set_row_opt(
+                    current_row,
+                    xlsxwriter::worksheet::LXW_DEF_ROW_HEIGHT,
+                    None,
+                    &xlsxwriter::worksheet::RowColOptions::new(true, 0, false),
+                )?;
+            }
 
-            let cell_type: CellType = if i == 0 && source_def.has_header {
-                CellType::String
+            let is_header_row = i == 0 && source_def.has_header;
+            if !is_header_row && links.is_none() {
+                links = Some(column_links(source_def.columns.as_ref(), &header_line)?);
+                comment_columns = comment_sources(source_def.columns.as_ref(), &header_line)?;
+            }
+            let row_values: Vec<&str> = if data_offset > 0 {
+                std::iter::once(source_label.as_str())
+                    .chain(row.iter())
+                    .collect()
             } else {
-                source_def
+                row.iter().collect()
+            };
+
+            for (j, cell) in row.iter().enumerate() {
+                let column = ((j + data_offset) as WorksheetCol) + start_column;
+                maximum_col = maximum_col.max(j + data_offset);
+                let link = links
+                    .as_ref()
+                    .filter(|_| !is_header_row && !cell.is_empty())
+                    .and_then(|x| x.get(j))
+                    .and_then(|x| x.as_ref());
+
+                let link_prefix: Option<String> = source_def
                     .columns
                     .as_ref()
-                    .map(|x| x.get(j).map(|y| y.cell_type))
+                    .map(|x| x.get(j))
                     .flatten()
-                    .unwrap_or(CellType::Auto)
-            };
+                    .map(|x| x.link_prefix.clone())
+                    .flatten();
 
-            match parse_cell(cell, cell_type) {
-                Ok(value) => {
-                    if let Some(link_prefix) = link_prefix.as_deref() {
-                        worksheet.write_url(
-                            (i as WorksheetRow) + offset_row,
-                            (j as WorksheetCol) + source_def.start_column,
-                            &format!("{}{}", link_prefix, cell),
-                            None,
-                        )?;
+                if is_header_row {
+                    if let Some(comment) = source_def
+                        .columns
+                        .as_ref()
+                        .map(|x| x.get(j))
+                        .flatten()
+                        .map(|x| x.header_comment.as_ref())
+                        .flatten()
+                    {
+                        write_comment(worksheet, current_row, column, comment)?;
                     }
-                    write_cell(
+
+                    if source_def
+                        .filter_list
+                        .as_ref()
+                        .map(|x| x.column_header == cell)
+                        .unwrap_or(false)
+                        && !source_def.table
+                    {
+                        filter_column_index = Some(j);
+                    }
+
+                    header_line.push(cell.to_string());
+                }
+
+                let column_def = source_def.columns.as_ref().and_then(|x| x.get(j));
+                let inferred_type = inferred_types.get(j).copied().flatten();
+                let cell_type: CellType = if is_header_row {
+                    CellType::String
+                } else {
+                    match column_def.map(|x| x.cell_type) {
+                        Some(CellType::Auto) | None => inferred_type.unwrap_or(CellType::Auto),
+                        Some(x) => x,
+                    }
+                };
+
+                if let Some(comment) = comment_columns
+                    .get(j)
+                    .copied()
+                    .flatten()
+                    .and_then(|x| row_values.get(x))
+                    .filter(|x| !is_header_row && !x.is_empty())
+                {
+                    write_comment(
                         worksheet,
-                        (i as WorksheetRow) + offset_row,
-                        (j as WorksheetCol) + source_def.start_column,
-                        &value,
-                        source_def
-                            .columns
-                            .as_ref()
-                            .map(|x| {
-                                x.get(j).map(|y| {
-                                    formats.get_format(
-                                        y.format.as_ref(),
-                                        if link_prefix.is_some() {
-                                            CellType::Url
-                                        } else {
-                                            actual_cell_type(&value, y.cell_type)
-                                        },
-                                    )
-                                })
-                            })
-                            .flatten()
-                            .flatten(),
+                        current_row,
+                        column,
+                        &Comment::Text(comment.to_string()),
                     )?;
                 }
-                Err(e) => {
-                    eprintln!(
-                        "warning: {}: row {}, column {}: {}",
-                        source_def.file.as_deref().unwrap_or("embedded data"),
-                        i,
-                        j,
-                        e
-                    );
+
+                match parse_cell(cell, cell_type) {
+                    Ok(value) => {
+                        let link_text = link
+                            .and_then(|x| x.text.as_ref())
+                            .map(|x| x.render(&row_values));
+                        if let Some(text) = link_text.as_deref() {
+                            widths.record_text(
+                                column,
+                                text,
+                                column_def.and_then(|x| x.format.as_ref()),
+                                false,
+                            );
+                        } else {
+                            widths.record_value(
+                                column,
+                                &value,
+                                cell_type,
+                                column_def.and_then(|x| x.format.as_ref()),
+                                is_header_row,
+                            );
+                        }
+                        if let Some(link) = link {
+                            let format = formats.get_format(
+                                column_def.and_then(|x| x.format.as_ref()),
+                                CellType::Url,
+                            );
+                            worksheet.write_url(
+                                current_row,
+                                column,
+                                &link.url.render(&row_values),
+                                format,
+                            )?;
+                            if let Some(text) = link_text.as_deref() {
+                                worksheet.write_string(current_row, column, text, format)?;
+                            } else {
+                                write_cell(worksheet, current_row, column, &value, format)?;
+                            }
+                            continue;
+                        }
+                        if let Some(link_prefix) = link_prefix.as_deref() {
+                            worksheet.write_url(
+                                current_row,
+                                column,
+                                &format!("{}{}", link_prefix, cell),
+                                None,
+                            )?;
+                        }
+                        write_cell(
+                            worksheet,
+                            current_row,
+                            column,
+                            &value,
+                            if column_def.is_some() || inferred_type.is_some() {
+                                let format_def = column_def.and_then(|x| x.format.as_ref());
+                                if link_prefix.is_some() {
+                                    formats.get_format(format_def, CellType::Url)
+                                } else {
+                                    formats.get_value_format(format_def, &value, cell_type)
+                                }
+                            } else {
+                                None
+                            },
+                        )?;
+                    }
+                    Err(e) => {
+                        eprintln!("warning: {}: row {}, column {}: {}", input.name, k, j, e);
+                    }
                 }
             }
+            if !is_header_row {
+                data_rows += 1;
+            }
+            i += 1;
+            current_row += 1;
+        }
+    }
+
+    if verbose {
+        for (j, inferred_type) in inferred_types.iter().enumerate() {
+            eprintln!(
+                "{}: column {} \"{}\": {:?}",
+                inputs[0].name,
+                j + data_offset + 1,
+                header_line
+                    .get(j + data_offset)
+                    .map(|x| x.as_str())
+                    .unwrap_or(""),
+                inferred_type.unwrap_or(CellType::Auto)
+            );
         }
     }
 
+    let extent = SourceExtent {
+        first_row: start_row,
+        first_col: start_column,
+        last_row,
+        last_col: start_column + maximum_col as WorksheetCol,
+    };
+    let sheet = match overflow.last_mut().filter(|_| first_extent.is_some()) {
+        Some(x) => x,
+        None => &mut *worksheet,
+    };
     super::table::setup_table(
-        worksheet,
+        sheet,
         source_def,
         formats,
         &header_line,
-        filter_column_index.map(|x| x as WorksheetCol),
-        maximum_row as WorksheetRow,
-        maximum_col as WorksheetCol,
+        filter_column_index.map(|x| (x + data_offset) as WorksheetCol),
+        &SourceExtent {
+            first_row: table_first_row,
+            ..extent
+        },
     )?;
 
+    Ok(InsertedSource {
+        extent: first_extent.unwrap_or(extent),
+        data_rows,
+        header: header_line,
+        sample_columns: Vec::new(),
+    })
+}
+
+/// Writes a header row above the last header row of a multi-row header
+fn write_leading_header_row(
+    worksheet: &mut Worksheet,
+    source_def: &SheetSourceDef,
+    formats: &FormatManager,
+    values: &[String],
+    row: WorksheetRow,
+    start_column: WorksheetCol,
+    widths: &mut ColumnWidths,
+) -> anyhow::Result<()> {
+    for (j, cell) in values.iter().enumerate() {
+        let column = j as WorksheetCol + start_column;
+        let format_def = source_def
+            .columns
+            .as_ref()
+            .and_then(|x| x.get(j))
+            .and_then(|x| x.format.as_ref());
+        worksheet.write_string(
+            row,
+            column,
+            cell,
+            formats.get_format(format_def, CellType::String),
+        )?;
+        widths.record_text(column, cell, format_def, true);
+    }
+    Ok(())
+}
+
+fn write_header_row(
+    worksheet: &mut Worksheet,
+    source_def: &SheetSourceDef,
+    header_line: &[String],
+    row: WorksheetRow,
+    start_column: WorksheetCol,
+    widths: &mut ColumnWidths,
+) -> anyhow::Result<()> {
+    let data_offset: usize = if source_def.source_file_column.is_some() {
+        1
+    } else {
+        0
+    };
+    if source_def.has_header {
+        for (i, value) in header_line.iter().enumerate() {
+            worksheet.write_string(row, i as WorksheetCol + start_column, value, None)?;
+            widths.record_text(i as WorksheetCol + start_column, value, None, true);
+        }
+    } else if let Some(source_file_column) = source_def.source_file_column.as_ref() {
+        worksheet.write_string(row, start_column, &source_file_column.header, None)?;
+        widths.record_text(start_column, &source_file_column.header, None, true);
+    }
+    if let Some(columns) = source_def.columns.as_ref() {
+        for (i, one) in columns.iter().enumerate() {
+            let column = (i + data_offset) as WorksheetCol + start_column;
+            if !source_def.has_header {
+                if let Some(value) = one.header_value.as_ref() {
+                    let value = parse_cell_value(value, one.header_type)?;
+                    write_cell(worksheet, row, column, &value, None)?;
+                    widths.record_value(column, &value, one.header_type, None, true);
+                }
+            }
+            if let Some(comment) = one.header_comment.as_ref() {
+                write_comment(worksheet, row, column, comment)?;
+            }
+        }
+    }
     Ok(())
 }
@@ -1,35 +1,234 @@
-use super::vcf::{self, VCF2CSVConfig};
-use super::FormatManager;
+use super::autofit::ColumnWidths;
+use super::comment::comment_sources;
+use super::link::column_links;
+use super::overflow::OverflowSheets;
+use super::source_file::{source_inputs, SourceInput};
+use super::vcf::bcf::BcfReader;
+use super::vcf::genotype::{load_pedigree, GenotypeFilter, InheritanceFilter, PedigreeMember};
+use super::vcf::index::{find_index, IndexedReader};
+use super::vcf::metadata::{metadata_rows, METADATA_COLUMNS};
+use super::vcf::region::{load_bed, Region, RegionFilter, RegionSet};
+use super::vcf::tablewriter::TableWriter;
+use super::vcf::{self, RecordSource, VCF2CSVConfig};
+use super::{FormatManager, InsertedSource, SourceExtent};
 use crate::model::*;
+use ::vcf::VCFHeader;
 use anyhow::Context;
-use std::collections::HashSet;
-use std::io::BufRead;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use xlsxwriter::worksheet::{Worksheet, WorksheetCol, WorksheetRow};
 
-pub fn insert_vcf<P: AsRef<Path>>(
-    worksheet: &mut Worksheet,
-    source_def: &SheetSourceDef,
-    formats: &FormatManager,
-    base_path: P,
-    canonical_transcripts: Option<HashSet<Vec<u8>>>,
-) -> anyhow::Result<()> {
-    let mut vcf_data_reader: Box<dyn BufRead> = if let Some(file) = source_def.file.as_deref() {
-        Box::new(std::io::BufReader::new(
-            autocompress::autodetect_open(base_path.as_ref().join(file))
-                .with_context(|| format!("Cannot open \"{}\"", file))?,
-        ))
-    } else if let Some(data) = source_def.data.as_deref() {
-        Box::new(&data.as_bytes()[..])
-    } else {
-        return Err(anyhow::anyhow!("No data found for VCF"));
+fn load_regions(regions_def: &RegionsDef, base_path: &Path) -> anyhow::Result<RegionSet> {
+    let regions = match regions_def {
+        RegionsDef::List(list) => list
+            .iter()
+            .map(|x| Region::parse(x))
+            .collect::<anyhow::Result<Vec<_>>>()?,
+        RegionsDef::Bed(path) => load_bed(std::io::BufReader::new(
+            autocompress::autodetect_open(base_path.join(path))
+                .with_context(|| format!("Cannot open \"{}\"", path))?,
+        ))?,
     };
+    Ok(RegionSet::new(&regions))
+}
 
-    if source_def.table && source_def.filter_list.is_some() {
-        eprintln!("filter_list cannot be enabled when table mode is enabled");
+/// Loads a PED file relative to the configuration file
+fn load_pedigree_file(path: &str, base_path: &Path) -> anyhow::Result<Vec<PedigreeMember>> {
+    load_pedigree(std::io::BufReader::new(
+        autocompress::autodetect_open(base_path.join(path))
+            .with_context(|| format!("Cannot open \"{}\"", path))?,
+    ))
+    .with_context(|| format!("Cannot read pedigree \"{}\"", path))
+}
+
+/// Loads sample aliases from an inline map or a two-column (tab or comma separated) file
+fn load_sample_names(
+    sample_names_def: &SampleNamesDef,
+    base_path: &Path,
+) -> anyhow::Result<HashMap<Vec<u8>, Vec<u8>>> {
+    match sample_names_def {
+        SampleNamesDef::Map(map) => Ok(map
+            .iter()
+            .map(|(k, v)| (k.as_bytes().to_vec(), v.as_bytes().to_vec()))
+            .collect()),
+        SampleNamesDef::File(path) => {
+            let reader = std::io::BufReader::new(
+                autocompress::autodetect_open(base_path.join(path))
+                    .with_context(|| format!("Cannot open \"{}\"", path))?,
+            );
+            let mut names = HashMap::new();
+            for (i, line) in std::io::BufRead::lines(reader).enumerate() {
+                let line = line?;
+                let line = line.trim_end();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let columns: Vec<_> = line.split(['\t', ',']).collect();
+                if columns.len() < 2 {
+                    return Err(anyhow::anyhow!(
+                        "Invalid sample name line at {} in \"{}\": {}",
+                        i + 1,
+                        path,
+                        line
+                    ));
+                }
+                names.insert(
+                    columns[0].trim().as_bytes().to_vec(),
+                    columns[1].trim().as_bytes().to_vec(),
+                );
+            }
+            Ok(names)
+        }
     }
+}
+
+fn sample_selection(samples_def: &SamplesDef) -> SampleSelectionDef {
+    match samples_def {
+        SamplesDef::List(list) => SampleSelectionDef {
+            include: Some(list.clone()),
+            ..Default::default()
+        },
+        SamplesDef::Def(def) => def.clone(),
+    }
+}
 
-    let mut vcf_reader = ::vcf::VCFReader::new(&mut vcf_data_reader)?;
+/// Included samples missing from a file are skipped when files are merged
+fn select_samples(
+    samples: &[Vec<u8>],
+    samples_def: &SamplesDef,
+    skip_missing: bool,
+) -> anyhow::Result<HashSet<Vec<u8>>> {
+    let selection = sample_selection(samples_def);
+    let pattern = selection
+        .pattern
+        .as_deref()
+        .map(Regex::new)
+        .transpose()
+        .context("Invalid sample pattern")?;
+    if let Some(include) = selection.include.as_ref().filter(|_| !skip_missing) {
+        if let Some(missing) = include
+            .iter()
+            .find(|x| !samples.contains(&x.as_bytes().to_vec()))
+        {
+            return Err(anyhow::anyhow!(
+                "Sample \"{}\" is not found in VCF",
+                missing
+            ));
+        }
+    }
+    Ok(samples
+        .iter()
+        .filter(|x| {
+            let name = String::from_utf8_lossy(x);
+            selection
+                .include
+                .as_ref()
+                .map(|y| y.iter().any(|z| *z == name))
+                .unwrap_or(true)
+                && !selection
+                    .exclude
+                    .as_ref()
+                    .map(|y| y.iter().any(|z| *z == name))
+                    .unwrap_or(false)
+                && pattern.as_ref().map(|y| y.is_match(&name)).unwrap_or(true)
+        })
+        .cloned()
+        .collect())
+}
+
+/// Label of a merged VCF file written to the Group Name column
+fn group_label(input: &SourceInput) -> String {
+    let name = Path::new(&input.name)
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_else(|| input.name.clone());
+    let name = name.strip_suffix(".gz").unwrap_or(&name);
+    name.strip_suffix(".vcf")
+        .or_else(|| name.strip_suffix(".bcf"))
+        .unwrap_or(name)
+        .to_string()
+}
+
+fn open_vcf<'a>(
+    source_def: &SheetSourceDef,
+    input: &SourceInput<'a>,
+    regions: Option<RegionSet>,
+) -> anyhow::Result<Box<dyn RecordSource + 'a>> {
+    let is_bcf = match source_def.format {
+        SheetSourceType::BCF => true,
+        SheetSourceType::Auto => input.name.ends_with(".bcf"),
+        _ => false,
+    };
+    if is_bcf {
+        let bcf_reader = BcfReader::new(input.open()?)?;
+        return Ok(match regions {
+            Some(regions) => Box::new(RegionFilter::new(bcf_reader, regions)),
+            None => Box::new(bcf_reader),
+        });
+    }
+    let index_path = input.path.as_deref().and_then(find_index);
+    Ok(match (regions, index_path) {
+        (Some(regions), Some(index_path)) => Box::new(IndexedReader::new(
+            input.path.as_deref().unwrap(),
+            &index_path,
+            &regions,
+        )?),
+        (Some(regions), None) => Box::new(RegionFilter::new(
+            ::vcf::VCFReader::new(input.open()?)?,
+            regions,
+        )),
+        (None, _) => Box::new(::vcf::VCFReader::new(input.open()?)?),
+    })
+}
+
+fn create_config(
+    source_def: &SheetSourceDef,
+    header: &VCFHeader,
+    sample_names: Option<&HashMap<Vec<u8>, Vec<u8>>>,
+    canonical_transcripts: Option<HashSet<Vec<u8>>>,
+    group_name: Option<Vec<u8>>,
+    pedigree: Option<&[PedigreeMember]>,
+    merge: bool,
+) -> anyhow::Result<VCF2CSVConfig> {
+    let samples = source_def
+        .vcf_config
+        .as_ref()
+        .and_then(|x| x.samples.as_ref())
+        .map(|x| select_samples(header.samples(), x, merge))
+        .transpose()?;
+    let replace_sample_name = sample_names.map(|names| {
+        header
+            .samples()
+            .iter()
+            .map(|x| names.get(x).unwrap_or(x).clone())
+            .collect::<Vec<_>>()
+    });
+    let genotype_filters = source_def
+        .vcf_config
+        .as_ref()
+        .map(|x| {
+            x.genotype_filters
+                .iter()
+                .map(|y| GenotypeFilter::new(y, header, replace_sample_name.as_deref()))
+                .collect::<anyhow::Result<Vec<_>>>()
+        })
+        .transpose()?
+        .unwrap_or_default();
+    let inheritance = pedigree
+        .map(|x| {
+            InheritanceFilter::new(
+                x,
+                header,
+                replace_sample_name.as_deref(),
+                source_def
+                    .vcf_config
+                    .as_ref()
+                    .map(|y| &y.inheritance[..])
+                    .unwrap_or_default(),
+            )
+        })
+        .transpose()?;
     let priority_info_list = source_def
         .vcf_config
         .as_ref()
@@ -59,8 +258,7 @@ pub fn insert_vcf<P: AsRef<Path>>(
         .flatten()
         .map(|x| x.iter().map(|y| y.as_bytes().to_vec()).collect())
         .unwrap_or_else(|| {
-            let mut l = vcf_reader
-                .header()
+            let mut l = header
                 .info_list()
                 .cloned()
                 .filter(|x| !priority_info_hash.contains(&x))
@@ -75,8 +273,7 @@ pub fn insert_vcf<P: AsRef<Path>>(
         .flatten()
         .map(|x| x.iter().map(|y| y.as_bytes().to_vec()).collect())
         .unwrap_or_else(|| {
-            let mut l = vcf_reader
-                .header()
+            let mut l = header
                 .format_list()
                 .cloned()
                 .filter(|x| !priority_format_hash.contains(&x))
@@ -85,7 +282,7 @@ pub fn insert_vcf<P: AsRef<Path>>(
             l
         });
 
-    let config = VCF2CSVConfig {
+    Ok(VCF2CSVConfig {
         split_multi_allelic: source_def
             .vcf_config
             .as_ref()
@@ -101,11 +298,144 @@ pub fn insert_vcf<P: AsRef<Path>>(
         priority_format_list,
         info_list,
         format_list,
-        replace_sample_name: None,
-        group_names: None,
-    };
+        replace_sample_name,
+        group_names: group_name.clone().map(|x| vec![x]),
+        annotation_fields: source_def
+            .vcf_config
+            .as_ref()
+            .and_then(|x| x.annotation_fields.clone())
+            .unwrap_or_default(),
+        annotation_transcript: source_def
+            .vcf_config
+            .as_ref()
+            .map(|x| x.annotation_transcript)
+            .unwrap_or_default(),
+        split_annotations: source_def
+            .vcf_config
+            .as_ref()
+            .map(|x| x.split_annotations)
+            .unwrap_or(false),
+        samples,
+        missing_field: source_def
+            .vcf_config
+            .as_ref()
+            .map(|x| x.missing_field)
+            .unwrap_or_default(),
+        genotype_filters,
+        inheritance,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn insert_vcf<'b, P: AsRef<Path>>(
+    worksheet: &mut Worksheet<'b>,
+    overflow: &mut OverflowSheets<'b>,
+    source_def: &SheetSourceDef,
+    formats: &FormatManager,
+    base_path: P,
+    canonical_transcripts: Option<HashSet<Vec<u8>>>,
+    start_row: WorksheetRow,
+    start_column: WorksheetCol,
+    widths: &mut ColumnWidths,
+) -> anyhow::Result<InsertedSource> {
+    let inputs = source_inputs(source_def, base_path.as_ref())?;
+    if inputs.is_empty() {
+        return Err(anyhow::anyhow!("No data found for VCF"));
+    }
+    // Multiple files are merged into one table labelled by the Group Name column
+    let merge = inputs.len() > 1;
+
+    if source_def.table && source_def.filter_list.is_some() {
+        eprintln!("filter_list cannot be enabled when table mode is enabled");
+    }
+
+    let regions = source_def
+        .vcf_config
+        .as_ref()
+        .and_then(|x| x.regions.as_ref())
+        .map(|x| load_regions(x, base_path.as_ref()))
+        .transpose()?;
+    let sample_names = source_def
+        .vcf_config
+        .as_ref()
+        .and_then(|x| x.sample_names.as_ref())
+        .map(|x| load_sample_names(x, base_path.as_ref()))
+        .transpose()?;
+    let group_names = source_def
+        .vcf_config
+        .as_ref()
+        .and_then(|x| x.group_names.clone())
+        .unwrap_or_default();
+    let pedigree = source_def
+        .vcf_config
+        .as_ref()
+        .and_then(|x| x.pedigree.as_ref())
+        .map(|x| load_pedigree_file(x, base_path.as_ref()))
+        .transpose()?;
+
+    let mut sources = Vec::new();
+    for input in inputs.iter() {
+        let vcf_reader = open_vcf(source_def, input, regions.clone())?;
+        let group_name = if merge {
+            Some(
+                group_names
+                    .get(&input.name)
+                    .cloned()
+                    .unwrap_or_else(|| group_label(input)),
+            )
+        } else {
+            source_def
+                .vcf_config
+                .as_ref()
+                .and_then(|x| x.group_name.clone())
+        }
+        .map(|x| x.into_bytes());
+        let config = create_config(
+            source_def,
+            vcf_reader.header(),
+            sample_names.as_ref(),
+            canonical_transcripts.clone(),
+            group_name.clone(),
+            pedigree.as_deref(),
+            merge,
+        )?;
+        let header_contents = vcf::create_header_line(vcf_reader.header(), &config)
+            .with_context(|| format!("Cannot create VCF columns for \"{}\"", input.name))?;
+        sources.push((vcf_reader, config, header_contents, group_name));
+    }
+
+    if let Some(include) = source_def
+        .vcf_config
+        .as_ref()
+        .and_then(|x| x.samples.as_ref())
+        .and_then(|x| sample_selection(x).include)
+    {
+        if let Some(missing) = include.iter().find(|x| {
+            !sources
+                .iter()
+                .any(|y| y.0.header().samples().contains(&x.as_bytes().to_vec()))
+        }) {
+            return Err(anyhow::anyhow!(
+                "Sample \"{}\" is not found in VCF",
+                missing
+            ));
+        }
+    }
+
+    if let Some(sample_names) = sample_names.as_ref() {
+        for one in sample_names
+            .keys()
+            .filter(|x| !sources.iter().any(|y| y.0.header().samples().contains(x)))
+        {
+            eprintln!(
+                "WARNING: Sample \"{}\" is not found in VCF",
+                String::from_utf8_lossy(one)
+            );
+        }
+    }
+
+    let header_contents = vcf::union_header_contents(sources.iter().map(|x| &x.2[..]));
 
-    let header_contents = vcf::create_header_line(&vcf_reader.header(), &config);
     let filter_column_index = source_def
         .filter_list
         .as_ref()
@@ -125,46 +455,123 @@ pub fn insert_vcf<P: AsRef<Path>>(
         .map(|x| x.items.iter().map(|y| y.to_string()).collect())
         .unwrap_or_default();
 
+    let first_overflow_sheet = overflow.len();
     let mut writer = vcf::tablewriter::XlsxSheetWriter::new(
         worksheet,
         formats,
-        source_def.start_row,
-        source_def.start_column,
+        start_row,
+        start_column,
         filter_column_index,
         &filter_list,
     );
+    writer.set_overflow(source_def.overflow, overflow);
+    if widths.is_active() {
+        writer.set_auto_fit(widths);
+    }
+    let column_header: Vec<_> = header_contents.iter().map(|x| x.to_string()).collect();
+    writer.set_links(column_links(source_def.columns.as_ref(), &column_header)?);
+    writer.set_comment_sources(comment_sources(
+        source_def.columns.as_ref(),
+        &column_header,
+    )?);
 
     vcf::vcf2table_set_data_type(&header_contents, &mut writer)?;
-    let row_num = vcf::vcf2table(
-        &mut vcf_reader,
-        &header_contents,
-        &config,
-        None,
-        true,
-        &mut writer,
-    )?;
+    writer.set_header(&column_header);
+    writer.write_header()?;
+    for (vcf_reader, config, one, group_name) in sources.iter_mut() {
+        if !writer.is_next_row_allowed() {
+            break;
+        }
+        vcf::vcf2table(
+            vcf_reader.as_mut(),
+            &vcf::merge_header_contents(&header_contents, one),
+            config,
+            group_name.as_ref(),
+            false,
+            &mut writer,
+        )?;
+    }
+    let sheet_last_rows = writer.sheet_last_rows();
+    let data_rows = writer.written_rows();
 
     let column_widths = vcf::column_widths(&header_contents);
-    for (i, one) in column_widths.iter().enumerate() {
-        worksheet.set_column(
-            source_def.start_column + i as WorksheetCol,
-            source_def.start_column + i as WorksheetCol,
-            8.0 * one,
-            None,
+    let mut extents = Vec::new();
+    for (i, last_row) in sheet_last_rows.iter().enumerate() {
+        let sheet = if i == 0 {
+            &mut *worksheet
+        } else {
+            &mut overflow.sheets_mut()[first_overflow_sheet + i - 1]
+        };
+        for (j, one) in column_widths.iter().enumerate() {
+            let column = start_column + j as WorksheetCol;
+            sheet.set_column(column, column, 8.0 * one, None)?;
+            widths.record_source_width(column, 8.0 * one);
+        }
+
+        let extent = SourceExtent {
+            first_row: start_row,
+            first_col: start_column,
+            last_row: *last_row,
+            last_col: start_column + column_header.len() as WorksheetCol - 1,
+        };
+        super::table::setup_table(
+            sheet,
+            source_def,
+            formats,
+            &column_header,
+            filter_column_index.map(|x| x as WorksheetCol),
+            &extent,
         )?;
+        extents.push(extent);
     }
 
-    let column_header: Vec<_> = header_contents.iter().map(|x| x.to_string()).collect();
+    Ok(InsertedSource {
+        extent: extents[0],
+        data_rows,
+        header: column_header,
+        sample_columns: vcf::sample_columns(&header_contents),
+    })
+}
 
-    super::table::setup_table(
-        worksheet,
-        source_def,
-        formats,
-        &column_header,
-        filter_column_index.map(|x| x as WorksheetCol),
-        row_num as WorksheetRow,
-        column_header.len() as WorksheetCol - 1,
-    )?;
+/// Writes the header metadata of the VCF files of a source to a companion sheet
+pub fn write_vcf_metadata<P: AsRef<Path>>(
+    worksheet: &mut Worksheet,
+    source_def: &SheetSourceDef,
+    formats: &FormatManager,
+    base_path: P,
+) -> anyhow::Result<usize> {
+    let inputs = source_inputs(source_def, base_path.as_ref())?;
+    let with_file = inputs.len() > 1;
+    let column_header: Vec<String> = with_file
+        .then_some("File")
+        .into_iter()
+        .chain(METADATA_COLUMNS)
+        .map(|x| x.to_string())
+        .collect();
+
+    let empty_filter = HashSet::new();
+    let mut writer =
+        vcf::tablewriter::XlsxSheetWriter::new(worksheet, formats, 0, 0, None, &empty_filter);
+    writer.set_header(&column_header);
+    writer.write_header()?;
+    for input in inputs.iter() {
+        let vcf_reader = open_vcf(source_def, input, None)?;
+        for row in metadata_rows(vcf_reader.header()) {
+            let items: Vec<&str> = with_file
+                .then_some(input.name.as_str())
+                .into_iter()
+                .chain(row.iter().map(|x| x.as_str()))
+                .collect();
+            writer.write_row(&items)?;
+        }
+    }
+    let rows = writer.written_rows();
+    let last_row = rows as WorksheetRow;
 
-    Ok(())
+    let last_col = column_header.len() as WorksheetCol - 1;
+    worksheet.set_column(0, last_col - 1, 16.0, None)?;
+    worksheet.set_column(last_col, last_col, 100.0, None)?;
+    worksheet.autofilter(0, 0, last_row, last_col)?;
+    worksheet.freeze_panes(1, 0);
+    Ok(rows)
 }
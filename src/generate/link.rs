@@ -0,0 +1,137 @@
+use crate::model::SheetSourceColumnDef;
+
+pub fn find_column(header: &[String], name: &str) -> Option<usize> {
+    header
+        .iter()
+        .position(|x| x == name)
+        .or_else(|| name.parse::<usize>().ok().filter(|x| *x > 0).map(|x| x - 1))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart {
+    Text(String),
+    Column(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkTemplate {
+    parts: Vec<TemplatePart>,
+    encode: bool,
+}
+
+impl LinkTemplate {
+    pub fn parse(template: &str, header: &[String], encode: bool) -> anyhow::Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(TemplatePart::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..].find('}').ok_or_else(|| {
+                anyhow::anyhow!("Unclosed placeholder in link template \"{}\"", template)
+            })? + start;
+            let name = &rest[start + 1..end];
+            let index = find_column(header, name).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown column \"{}\" in link template \"{}\"",
+                    name,
+                    template
+                )
+            })?;
+            parts.push(TemplatePart::Column(index));
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(TemplatePart::Text(rest.to_string()));
+        }
+        Ok(LinkTemplate { parts, encode })
+    }
+
+    pub fn render(&self, row: &[&str]) -> String {
+        let mut result = String::new();
+        for part in self.parts.iter() {
+            match part {
+                TemplatePart::Text(text) => result.push_str(text),
+                TemplatePart::Column(index) => {
+                    let value = row.get(*index).copied().unwrap_or("");
+                    if self.encode {
+                        result.push_str(&urlencoding::encode(value));
+                    } else {
+                        result.push_str(value);
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnLink {
+    pub url: LinkTemplate,
+    pub text: Option<LinkTemplate>,
+}
+
+impl ColumnLink {
+    pub fn parse(
+        column_def: &SheetSourceColumnDef,
+        header: &[String],
+    ) -> anyhow::Result<Option<Self>> {
+        let url = match column_def.link_template.as_deref() {
+            Some(template) => {
+                LinkTemplate::parse(template, header, !template.starts_with("internal:"))?
+            }
+            None => return Ok(None),
+        };
+        let text = column_def
+            .link_text
+            .as_deref()
+            .map(|x| LinkTemplate::parse(x, header, false))
+            .transpose()?;
+        Ok(Some(ColumnLink { url, text }))
+    }
+}
+
+pub fn column_links(
+    columns: Option<&Vec<SheetSourceColumnDef>>,
+    header: &[String],
+) -> anyhow::Result<Vec<Option<ColumnLink>>> {
+    columns
+        .map(|x| x.iter().map(|y| ColumnLink::parse(y, header)).collect())
+        .unwrap_or_else(|| Ok(Vec::new()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_link_template() -> anyhow::Result<()> {
+        let header: Vec<String> = ["CHROM", "POS", "REF", "ALT"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        let row = ["chr1", "12345", "A", "C,<DEL>"];
+
+        let template = LinkTemplate::parse(
+            "https://gnomad.broadinstitute.org/variant/{CHROM}-{POS}-{REF}-{ALT}",
+            &header,
+            true,
+        )?;
+        assert_eq!(
+            template.render(&row),
+            "https://gnomad.broadinstitute.org/variant/chr1-12345-A-C%2C%3CDEL%3E"
+        );
+
+        let template = LinkTemplate::parse("internal:'{1}'!A{2}", &header, false)?;
+        assert_eq!(template.render(&row), "internal:'chr1'!A12345");
+
+        let template = LinkTemplate::parse("{CHROM}:{POS}", &header, false)?;
+        assert_eq!(template.render(&row[..1]), "chr1:");
+
+        assert!(LinkTemplate::parse("{GENE}", &header, true).is_err());
+        assert!(LinkTemplate::parse("{0}", &header, true).is_err());
+        assert!(LinkTemplate::parse("https://example.com/{CHROM", &header, true).is_err());
+        Ok(())
+    }
+}
@@ -1,7 +1,16 @@
+mod autofit;
 mod cell;
+mod column;
+mod comment;
+mod contents;
 mod format;
+mod infer;
 mod insert_csv;
 mod insert_vcf;
+mod link;
+mod outline;
+mod overflow;
+mod source_file;
 pub mod table;
 pub mod vcf;
 
@@ -13,141 +22,478 @@ use std::str;
 use xlsxwriter::worksheet::{ImageOptions, Worksheet, WorksheetCol, WorksheetRow};
 
 use crate::model::*;
+use autofit::ColumnWidths;
 use cell::*;
+use column::ColumnSettings;
 use format::*;
 use insert_csv::*;
 use insert_vcf::*;
+use outline::Outline;
+use overflow::OverflowSheets;
 
-pub fn generate_worksheet<P: AsRef<Path>>(
-    worksheet: &mut Worksheet,
-    worksheet_def: &WorksheetDef,
-    formats: &FormatManager,
-    base_path: P,
-    canonical_transcripts: Option<HashSet<Vec<u8>>>,
-) -> anyhow::Result<()> {
-    if let Some(source) = worksheet_def.source.as_ref() {
-        let source_array: Vec<SheetSourceDef> = source.clone().into();
-        for source in source_array.iter() {
-            match source.suggest_format() {
-                SheetSourceType::CSV | SheetSourceType::TSV => {
-                    insert_csv(worksheet, &source, formats, base_path.as_ref())?;
-                }
-                SheetSourceType::VCF => {
-                    insert_vcf(
-                        worksheet,
-                        &source,
-                        formats,
-                        base_path.as_ref(),
-                        canonical_transcripts.clone(),
-                    )?;
-                }
-                _ => unreachable!(),
-            }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SourceExtent {
+    pub first_row: WorksheetRow,
+    pub first_col: WorksheetCol,
+    pub last_row: WorksheetRow,
+    pub last_col: WorksheetCol,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct InsertedSource {
+    pub extent: SourceExtent,
+    pub data_rows: usize,
+    pub header: Vec<String>,
+    pub sample_columns: Vec<(usize, usize)>,
+}
+
+// "after-previous" clears all previous sources so that a source never overlaps an earlier one
+fn resolve_start_row(
+    position: SourcePosition<WorksheetRow>,
+    gap: u16,
+    previous: &[SourceExtent],
+) -> WorksheetRow {
+    match position {
+        SourcePosition::Absolute(row) => row,
+        SourcePosition::Relative(RelativePosition::AfterPrevious) => previous
+            .iter()
+            .map(|x| x.last_row + 1 + WorksheetRow::from(gap))
+            .max()
+            .unwrap_or(0),
+        SourcePosition::Relative(RelativePosition::SameAsPrevious) => {
+            previous.last().map(|x| x.first_row).unwrap_or(0)
+        }
+    }
+}
+
+fn resolve_start_column(
+    position: SourcePosition<WorksheetCol>,
+    gap: u16,
+    previous: &[SourceExtent],
+) -> WorksheetCol {
+    match position {
+        SourcePosition::Absolute(col) => col,
+        SourcePosition::Relative(RelativePosition::AfterPrevious) => previous
+            .iter()
+            .map(|x| x.last_col + 1 + gap)
+            .max()
+            .unwrap_or(0),
+        SourcePosition::Relative(RelativePosition::SameAsPrevious) => {
+            previous.last().map(|x| x.first_col).unwrap_or(0)
         }
     }
+}
 
+fn resolve_cell_positions(
+    cells: &[CellDef],
+    extents: &[SourceExtent],
+) -> anyhow::Result<Vec<(WorksheetRow, WorksheetCol)>> {
+    let mut positions = Vec::new();
     let mut first_cell = true;
     let mut last_row: WorksheetRow = 0;
     let mut last_col: WorksheetCol = 0;
     let mut last_explicit_col: WorksheetCol = 0;
 
-    for one_cell in worksheet_def.cells.iter() {
+    for one_cell in cells.iter() {
         //eprintln!("cell: {:?}", one_cell);
-        let row: WorksheetRow = if let Some(row_relative) = one_cell.row_relative {
-            //eprint!("row relative : {:?} ", row_relative);
-            if row_relative < 0 {
-                last_row - (-row_relative).try_into().unwrap_or(0)
+        let (row, column) = if let Some(anchor) = one_cell.anchor.as_ref() {
+            let extent = if let Some(index) = anchor.source {
+                extents.get(index)
             } else {
-                last_row + row_relative.try_into().unwrap_or(0)
+                extents.last()
             }
-        } else if let Some(row) = one_cell.row {
-            row
-        } else {
-            last_row
-        };
-        let column: WorksheetCol = if let Some(col_relative) = one_cell.column_relative {
-            let col = if col_relative < 0 {
-                last_col - (-col_relative).try_into().unwrap_or(0)
+            .ok_or_else(|| anyhow::anyhow!("No source found for cell anchor: {:?}", anchor))?;
+            let (anchor_row, anchor_col) = match anchor.position {
+                AnchorPosition::Below => (extent.last_row + 1, extent.first_col),
+                AnchorPosition::Above => (extent.first_row.saturating_sub(1), extent.first_col),
+                AnchorPosition::Right => (extent.first_row, extent.last_col + 1),
+            };
+            let row_offset = one_cell.row_relative.unwrap_or(0);
+            let col_offset = one_cell.column_relative.unwrap_or(0);
+            let row = if row_offset < 0 {
+                anchor_row - (-row_offset).try_into().unwrap_or(0)
+            } else {
+                anchor_row + row_offset.try_into().unwrap_or(0)
+            };
+            let column = if col_offset < 0 {
+                anchor_col - (-col_offset).try_into().unwrap_or(0)
             } else {
-                last_col + col_relative.try_into().unwrap_or(0)
+                anchor_col + col_offset.try_into().unwrap_or(0)
             };
-            last_explicit_col = col;
-            col
-        } else if let Some(col) = one_cell.column {
-            last_explicit_col = col;
-            col
+            last_explicit_col = column;
+            (row, column)
         } else {
-            if last_row != row {
-                last_explicit_col
-            } else if first_cell {
-                0
+            let row: WorksheetRow = if let Some(row_relative) = one_cell.row_relative {
+                //eprint!("row relative : {:?} ", row_relative);
+                if row_relative < 0 {
+                    last_row - (-row_relative).try_into().unwrap_or(0)
+                } else {
+                    last_row + row_relative.try_into().unwrap_or(0)
+                }
+            } else if let Some(row) = one_cell.row {
+                row
             } else {
-                last_col + 1
-            }
+                last_row
+            };
+            let column: WorksheetCol = if let Some(col_relative) = one_cell.column_relative {
+                let col = if col_relative < 0 {
+                    last_col - (-col_relative).try_into().unwrap_or(0)
+                } else {
+                    last_col + col_relative.try_into().unwrap_or(0)
+                };
+                last_explicit_col = col;
+                col
+            } else if let Some(col) = one_cell.column {
+                last_explicit_col = col;
+                col
+            } else {
+                if last_row != row {
+                    last_explicit_col
+                } else if first_cell {
+                    0
+                } else {
+                    last_col + 1
+                }
+            };
+            (row, column)
         };
         last_row = row;
         last_col = column;
         first_cell = false;
+        positions.push((row, column));
+    }
+    Ok(positions)
+}
 
-        if one_cell.merge_column.is_some() || one_cell.merge_row.is_some() {
-            let merge_col = one_cell.merge_column.unwrap_or(1);
-            let merge_row = one_cell.merge_row.unwrap_or(1);
-            worksheet.merge_range(
-                row,
+fn covers(extent: &SourceExtent, row: WorksheetRow, column: WorksheetCol) -> bool {
+    (extent.first_row..=extent.last_row).contains(&row)
+        && (extent.first_col..=extent.last_col).contains(&column)
+}
+
+fn check_cell_positions(
+    cells: &[CellDef],
+    positions: &[(WorksheetRow, WorksheetCol)],
+    back_link_cell: Option<(WorksheetRow, WorksheetCol)>,
+) -> anyhow::Result<()> {
+    if let Some((row, column)) = back_link_cell {
+        for (one_cell, (first_row, first_col)) in cells.iter().zip(positions.iter()) {
+            let extent = SourceExtent {
+                first_row: *first_row,
+                first_col: *first_col,
+                last_row: first_row + one_cell.merge_row.unwrap_or(1).max(1) - 1,
+                last_col: first_col + one_cell.merge_column.unwrap_or(1).max(1) - 1,
+            };
+            if covers(&extent, row, column) {
+                return Err(anyhow::anyhow!(
+                    "A cell is placed at row {} column {} reserved for the back link to contents",
+                    row + 1,
+                    column + 1
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_cell_def(
+    worksheet: &mut Worksheet,
+    one_cell: &CellDef,
+    row: WorksheetRow,
+    column: WorksheetCol,
+    formats: &FormatManager,
+    widths: &mut ColumnWidths,
+) -> anyhow::Result<()> {
+    if one_cell.merge_column.is_some() || one_cell.merge_row.is_some() {
+        let merge_col = one_cell.merge_column.unwrap_or(1);
+        let merge_row = one_cell.merge_row.unwrap_or(1);
+        worksheet.merge_range(
+            row,
+            column,
+            row + merge_row - 1,
+            column + merge_col - 1,
+            "",
+            None,
+        )?;
+
+        for row in row..(row + merge_row) {
+            for col in column..(column + merge_col) {
+                worksheet.write_blank(
+                    row,
+                    col,
+                    formats.get_format(one_cell.format.as_ref(), CellType::Null),
+                )?;
+            }
+        }
+    }
+    if let Some(url) = one_cell.url.as_deref() {
+        worksheet.write_url(row, column, url, None)?;
+    }
+    if let Some(value) = one_cell.value.as_ref() {
+        let parsed_value = parse_cell_value(&value, one_cell.cell_type)?;
+        if one_cell.merge_column.is_none() && one_cell.merge_row.is_none() {
+            widths.record_value(
                 column,
-                row + merge_row - 1,
-                column + merge_col - 1,
-                "",
-                None,
-            )?;
+                &parsed_value,
+                one_cell.cell_type,
+                one_cell.format.as_ref(),
+                false,
+            );
+        }
+        write_cell(
+            worksheet,
+            row,
+            column,
+            &parsed_value,
+            if one_cell.url.is_some() {
+                formats.get_format(one_cell.format.as_ref(), CellType::Url)
+            } else {
+                formats.get_value_format(
+                    one_cell.format.as_ref(),
+                    &parsed_value,
+                    one_cell.cell_type,
+                )
+            },
+        )?;
+        if let Some(comment) = one_cell.comment.as_ref() {
+            comment::write_comment(worksheet, row, column, comment)?;
+        }
+    }
+    Ok(())
+}
 
-            for row in row..(row + merge_row) {
-                for col in column..(column + merge_col) {
-                    worksheet.write_blank(
-                        row,
-                        col,
-                        formats.get_format(one_cell.format.as_ref(), CellType::Null),
-                    )?;
-                }
+fn resolve_internal_links(
+    worksheet_def: &WorksheetDef,
+    sheet_names: &[String],
+    continued_sheets: &[String],
+) -> anyhow::Result<WorksheetDef> {
+    let mut worksheet_def = worksheet_def.clone();
+    for one_cell in worksheet_def.cells.iter_mut() {
+        if let Some(link_to) = one_cell.link_to.as_ref() {
+            if one_cell.url.is_some() {
+                return Err(anyhow::anyhow!("url and link-to cannot be used together"));
+            }
+            one_cell.url = Some(contents::link_to_url(
+                link_to,
+                sheet_names,
+                continued_sheets,
+            )?);
+            if one_cell.value.is_none() {
+                one_cell.value = Some(CellValue::String(link_to.sheet.to_string()));
+            }
+        }
+    }
+    Ok(worksheet_def)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn generate_worksheet<'b, P: AsRef<Path>>(
+    worksheet: &mut Worksheet<'b>,
+    overflow: &mut OverflowSheets<'b>,
+    worksheet_def: &WorksheetDef,
+    formats: &FormatManager,
+    base_path: P,
+    canonical_transcripts: Option<HashSet<Vec<u8>>>,
+    constant_memory: bool,
+    back_link_cell: Option<(WorksheetRow, WorksheetCol)>,
+    verbose: bool,
+) -> anyhow::Result<usize> {
+    let mut source_array: Vec<SheetSourceDef> = worksheet_def
+        .source
+        .clone()
+        .map(|x| x.into())
+        .unwrap_or_default();
+    let mut written_cells = vec![false; worksheet_def.cells.len()];
+    let sheet_auto_fit = worksheet_def.auto_fit.as_ref().and_then(|x| x.options());
+    let mut widths = ColumnWidths::new();
+    widths.set_active(sheet_auto_fit.clone());
+    let mut column_settings = ColumnSettings::new();
+    let mut outline = Outline::new(worksheet_def.outline.as_ref());
+    let mut current_row: WorksheetRow = 0;
+
+    if constant_memory {
+        if !worksheet_def.row_heights.is_empty() {
+            return Err(anyhow::anyhow!(
+                "row-heights is not supported in constant memory mode"
+            ));
+        }
+        if !worksheet_def.row_groups.is_empty()
+            || source_array.iter().any(|x| !x.row_groups.is_empty())
+        {
+            return Err(anyhow::anyhow!(
+                "row-groups is not supported in constant memory mode"
+            ));
+        }
+        if worksheet_def
+            .cells
+            .iter()
+            .any(|x| x.merge_row.is_some() || x.merge_column.is_some())
+        {
+            return Err(anyhow::anyhow!(
+                "merge-row and merge-column are not supported in constant memory mode"
+            ));
+        }
+        for source in source_array.iter_mut() {
+            if source.table {
+                eprintln!("WARNING: table is not supported in constant memory mode. autofilter is used instead.");
+                source.table = false;
             }
         }
-        if let Some(url) = one_cell.url.as_deref() {
-            worksheet.write_url(row, column, url, None)?;
+
+        // Cells placed above the first source must be written before it in constant memory mode
+        let first_source_row = source_array
+            .first()
+            .map(|x| resolve_start_row(x.start_row, x.gap.unwrap_or(1), &[]))
+            .unwrap_or(WorksheetRow::MAX);
+        let unanchored = worksheet_def
+            .cells
+            .iter()
+            .position(|x| x.anchor.is_some())
+            .unwrap_or(worksheet_def.cells.len());
+        let positions = resolve_cell_positions(&worksheet_def.cells[..unanchored], &[])?;
+        check_cell_positions(
+            &worksheet_def.cells[..unanchored],
+            &positions,
+            back_link_cell,
+        )?;
+        let mut leading: Vec<_> = (0..positions.len())
+            .filter(|i| positions[*i].0 < first_source_row)
+            .collect();
+        // The back link is written before the sheet contents
+        if let Some((back_link_row, _)) = back_link_cell {
+            let first_row = leading
+                .iter()
+                .map(|i| positions[*i].0)
+                .fold(first_source_row, WorksheetRow::min);
+            if first_row < back_link_row {
+                return Err(anyhow::anyhow!(
+                    "Row {} is written before the back link to contents in row {}. back-link-cell must be above all cells and sources in constant memory mode",
+                    first_row,
+                    back_link_row
+                ));
+            }
         }
-        if let Some(value) = one_cell.value.as_ref() {
-            let parsed_value = parse_cell_value(&value, one_cell.cell_type)?;
-            write_cell(
+        leading.sort_by_key(|i| positions[*i].0);
+        for i in leading {
+            let (row, column) = positions[i];
+            write_cell_def(
                 worksheet,
+                &worksheet_def.cells[i],
                 row,
                 column,
-                &parsed_value,
-                formats.get_format(
-                    one_cell.format.as_ref(),
-                    if one_cell.url.is_some() {
-                        CellType::Url
-                    } else {
-                        actual_cell_type(&parsed_value, one_cell.cell_type)
-                    },
-                ),
+                formats,
+                &mut widths,
             )?;
-            if let Some(comment) = one_cell.comment.as_deref() {
-                worksheet.write_comment(row, column, comment)?;
+            written_cells[i] = true;
+            current_row = row;
+        }
+    }
+
+    let mut extents: Vec<SourceExtent> = Vec::new();
+    let mut data_rows = 0;
+    for (i, source) in source_array.iter().enumerate() {
+        let gap = source.gap.unwrap_or(1);
+        let start_row = resolve_start_row(source.start_row, gap, &extents);
+        let start_column = resolve_start_column(source.start_column, gap, &extents);
+        if constant_memory && start_row < current_row {
+            return Err(anyhow::anyhow!(
+                "Source {} starts at row {}, but row {} is already written. Sources must be placed in row order in constant memory mode",
+                i + 1,
+                start_row + 1,
+                current_row + 1
+            ));
+        }
+        widths.set_active(match source.auto_fit.as_ref() {
+            Some(x) => x.options(),
+            None => sheet_auto_fit.clone(),
+        });
+        let inserted = match source.suggest_format() {
+            SheetSourceType::CSV | SheetSourceType::TSV => insert_csv(
+                worksheet,
+                overflow,
+                &source,
+                formats,
+                base_path.as_ref(),
+                start_row,
+                start_column,
+                &mut widths,
+                verbose,
+            )?,
+            SheetSourceType::VCF | SheetSourceType::BCF => insert_vcf(
+                worksheet,
+                overflow,
+                &source,
+                formats,
+                base_path.as_ref(),
+                canonical_transcripts.clone(),
+                start_row,
+                start_column,
+                &mut widths,
+            )?,
+            _ => unreachable!(),
+        };
+        if let Some((row, column)) = back_link_cell {
+            if covers(&inserted.extent, row, column) {
+                return Err(anyhow::anyhow!(
+                    "Source {} overlaps row {} column {} reserved for the back link to contents",
+                    i + 1,
+                    row + 1,
+                    column + 1
+                ));
             }
         }
+        current_row = current_row.max(inserted.extent.last_row);
+        column_settings.add_source(source, start_column)?;
+        outline.add_source_groups(source, &inserted, &mut column_settings)?;
+        extents.push(inserted.extent);
+        data_rows += inserted.data_rows;
     }
 
-    for (i, one) in worksheet_def.column_widths.iter().enumerate() {
-        worksheet.set_column(
-            i as xlsxwriter::worksheet::WorksheetCol,
-            i as xlsxwriter::worksheet::WorksheetCol,
-            *one,
-            None,
+    widths.set_active(sheet_auto_fit);
+    let positions = resolve_cell_positions(&worksheet_def.cells, &extents)?;
+    check_cell_positions(&worksheet_def.cells, &positions, back_link_cell)?;
+    let mut remaining: Vec<_> = (0..positions.len())
+        .filter(|i| !written_cells[*i])
+        .collect();
+    if constant_memory {
+        remaining.sort_by_key(|i| positions[*i].0);
+    }
+    for i in remaining {
+        let (row, column) = positions[i];
+        if constant_memory && row < current_row {
+            return Err(anyhow::anyhow!(
+                "Cell at row {} column {} cannot be written after row {} in constant memory mode. Place cells above the first source or below the sources",
+                row + 1,
+                column + 1,
+                current_row + 1
+            ));
+        }
+        write_cell_def(
+            worksheet,
+            &worksheet_def.cells[i],
+            row,
+            column,
+            formats,
+            &mut widths,
         )?;
+        current_row = row;
+    }
+
+    for (i, one) in worksheet_def.column_widths.iter().enumerate() {
+        column_settings.get_mut(i as WorksheetCol).width = Some(*one);
+    }
+    outline.add_sheet_groups(worksheet_def, &mut column_settings)?;
+    for sheet in std::iter::once(&mut *worksheet).chain(overflow.sheets_mut().iter_mut()) {
+        column_settings.apply(sheet, &widths, formats)?;
+        outline.apply_settings(sheet);
+        if let Some(freeze) = worksheet_def.freeze.as_ref() {
+            sheet.freeze_panes(freeze.row, freeze.column);
+        }
     }
 
     for (i, one) in worksheet_def.row_heights.iter().enumerate() {
         worksheet.set_row(i as xlsxwriter::worksheet::WorksheetRow, *one, None)?;
     }
+    outline.apply_rows(worksheet, &worksheet_def.row_heights)?;
 
     for one_image in worksheet_def.images.iter() {
         let opt = ImageOptions {
@@ -169,10 +515,7 @@ pub fn generate_worksheet<P: AsRef<Path>>(
         )?;
     }
 
-    if let Some(freeze) = worksheet_def.freeze.as_ref() {
-        worksheet.freeze_panes(freeze.row, freeze.column);
-    }
-    Ok(())
+    Ok(data_rows)
 }
 
 pub fn generate<P: AsRef<Path>>(
@@ -180,8 +523,14 @@ pub fn generate<P: AsRef<Path>>(
     filename: &str,
     base_path: P,
     canonical_transcripts: Option<HashSet<Vec<u8>>>,
+    verbose: bool,
 ) -> anyhow::Result<()> {
-    let workbook = xlsxwriter::Workbook::new(filename)?;
+    let workbook = xlsxwriter::Workbook::new_with_options(
+        filename,
+        workbook_def.constant_memory,
+        workbook_def.tmpdir.as_deref(),
+        false,
+    )?;
 
     let format_defs = collect_format(workbook_def);
     let mut format_manager = FormatManager::new();
@@ -189,21 +538,143 @@ pub fn generate<P: AsRef<Path>>(
         format_manager.add_format(one)?;
     }
 
-    for (sheet_index, one_sheet) in workbook_def.sheets.iter().enumerate() {
-        let name = if let Some(name) = one_sheet.name.as_ref() {
-            name.to_string()
-        } else {
-            format!("Sheet {}", sheet_index + 1)
-        };
-        let mut worksheet = workbook.add_worksheet(Some(&name))?;
-        generate_worksheet(
+    let sheet_names: Vec<String> = workbook_def
+        .sheets
+        .iter()
+        .enumerate()
+        .map(|(sheet_index, one_sheet)| {
+            if let Some(name) = one_sheet.name.as_ref() {
+                name.to_string()
+            } else {
+                format!("Sheet {}", sheet_index + 1)
+            }
+        })
+        .collect();
+    let sheet_sources: Vec<Vec<SheetSourceDef>> = workbook_def
+        .sheets
+        .iter()
+        .map(|x| x.source.clone().map(Vec::from).unwrap_or_default())
+        .collect();
+    // Metadata sheets are known in advance; continuation sheets exist only if a table overflows
+    let link_targets: Vec<String> = sheet_names
+        .iter()
+        .cloned()
+        .chain(
+            sheet_sources
+                .iter()
+                .flatten()
+                .filter_map(|x| x.vcf_config.as_ref().and_then(|y| y.metadata_sheet.clone())),
+        )
+        .chain(workbook_def.contents.as_ref().map(|x| x.name.to_string()))
+        .collect();
+    let continued_sheets: Vec<String> = sheet_names
+        .iter()
+        .zip(sheet_sources.iter())
+        .filter(|(_, sources)| {
+            sources
+                .iter()
+                .any(|x| x.overflow == OverflowPolicy::NewSheet)
+        })
+        .map(|(name, _)| name.to_string())
+        .collect();
+    let sheet_defs = workbook_def
+        .sheets
+        .iter()
+        .zip(sheet_names.iter())
+        .map(|(one_sheet, name)| {
+            resolve_internal_links(one_sheet, &link_targets, &continued_sheets)
+                .with_context(|| format!("Error on generating \"{}\"", name))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let back_link_cell = workbook_def
+        .contents
+        .as_ref()
+        .and_then(|x| x.back_link_cell.as_deref())
+        .map(|x| {
+            contents::parse_cell_reference(x)
+                .ok_or_else(|| anyhow::anyhow!("\"{}\" is not a valid cell reference", x))
+        })
+        .transpose()?;
+
+    let mut contents = workbook_def
+        .contents
+        .as_ref()
+        .map(|x| workbook.add_worksheet(Some(&x.name)))
+        .transpose()?;
+    let mut contents_entries = Vec::new();
+
+    for ((one_sheet, name), sources) in sheet_defs
+        .iter()
+        .zip(sheet_names.iter())
+        .zip(sheet_sources.iter())
+    {
+        let mut worksheet = workbook.add_worksheet(Some(name))?;
+        if let Some(contents_def) = workbook_def.contents.as_ref() {
+            contents::write_back_link(&mut worksheet, contents_def, &format_manager)?;
+        }
+        let mut overflow = OverflowSheets::new(&workbook, name);
+        let rows = generate_worksheet(
             &mut worksheet,
+            &mut overflow,
             one_sheet,
             &format_manager,
             base_path.as_ref(),
             canonical_transcripts.clone(),
+            workbook_def.constant_memory,
+            back_link_cell,
+            verbose,
         )
         .with_context(|| format!("Error on generating \"{}\"", name))?;
+        contents_entries.push(contents::ContentsEntry {
+            name: name.to_string(),
+            description: one_sheet.description.clone(),
+            rows: Some(rows),
+        });
+        contents_entries.extend(
+            overflow
+                .names()
+                .into_iter()
+                .map(|x| contents::ContentsEntry {
+                    name: x,
+                    description: Some(format!("Continuation of {}", name)),
+                    rows: None,
+                }),
+        );
+        for source in sources {
+            if let Some(metadata_sheet) = source
+                .vcf_config
+                .as_ref()
+                .and_then(|x| x.metadata_sheet.as_ref())
+            {
+                let mut worksheet = workbook.add_worksheet(Some(metadata_sheet))?;
+                let rows =
+                    write_vcf_metadata(&mut worksheet, source, &format_manager, base_path.as_ref())
+                        .with_context(|| format!("Error on generating \"{}\"", metadata_sheet))?;
+                contents_entries.push(contents::ContentsEntry {
+                    name: metadata_sheet.to_string(),
+                    description: Some(format!("VCF header metadata of {}", name)),
+                    rows: Some(rows),
+                });
+            }
+        }
+    }
+
+    // Links to continuation sheets can be checked only after tables are written
+    for one_cell in workbook_def.sheets.iter().flat_map(|x| x.cells.iter()) {
+        if let Some(link_to) = one_cell.link_to.as_ref() {
+            if !link_targets.contains(&link_to.sheet)
+                && !contents_entries.iter().any(|x| x.name == link_to.sheet)
+            {
+                return Err(anyhow::anyhow!(
+                    "Link target sheet \"{}\" was not created because the table did not overflow",
+                    link_to.sheet
+                ));
+            }
+        }
+    }
+
+    if let Some(contents) = contents.as_mut() {
+        contents::write_contents(contents, &contents_entries, &format_manager)?;
     }
 
     workbook.close()?;
@@ -0,0 +1,341 @@
+use super::column::ColumnSettings;
+use super::InsertedSource;
+use crate::model::*;
+use std::collections::BTreeMap;
+use xlsxwriter::worksheet::{
+    RowColOptions, Worksheet, WorksheetCol, WorksheetRow, LXW_DEF_ROW_HEIGHT,
+};
+
+const MAX_OUTLINE_LEVEL: u8 = 7;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct RowSetting {
+    level: u8,
+    hidden: bool,
+    collapsed: bool,
+}
+
+#[derive(Debug)]
+pub struct Outline {
+    summary_below: bool,
+    summary_right: bool,
+    rows: BTreeMap<WorksheetRow, RowSetting>,
+}
+
+fn resolve_position(
+    position: Option<&GroupPosition>,
+    header: &[String],
+    default: u32,
+) -> anyhow::Result<u32> {
+    match position {
+        None => Ok(default),
+        Some(GroupPosition::Index(x)) => Ok(*x),
+        Some(GroupPosition::Name(name)) => header
+            .iter()
+            .position(|x| x == name)
+            .map(|x| x as u32)
+            .ok_or_else(|| anyhow::anyhow!("Column \"{}\" is not found for grouping", name)),
+    }
+}
+
+fn check_range(group: &GroupDef, first: u32, last: u32) -> anyhow::Result<()> {
+    if group.level == 0 || group.level > MAX_OUTLINE_LEVEL {
+        return Err(anyhow::anyhow!(
+            "Group level must be between 1 and {}: {}",
+            MAX_OUTLINE_LEVEL,
+            group.level
+        ));
+    }
+    if first > last {
+        return Err(anyhow::anyhow!(
+            "The first position of a group ({}) is after the last position ({})",
+            first,
+            last
+        ));
+    }
+    Ok(())
+}
+
+impl Outline {
+    pub fn new(outline_def: Option<&OutlineDef>) -> Self {
+        Outline {
+            summary_below: outline_def.map(|x| x.summary_below).unwrap_or(true),
+            summary_right: outline_def.map(|x| x.summary_right).unwrap_or(true),
+            rows: BTreeMap::new(),
+        }
+    }
+
+    fn add_column_group(
+        &self,
+        columns: &mut ColumnSettings,
+        first: WorksheetCol,
+        last: WorksheetCol,
+        level: u8,
+        collapsed: bool,
+    ) {
+        for column in first..=last {
+            let setting = columns.get_mut(column);
+            setting.level = setting.level.max(level);
+            setting.hidden |= collapsed;
+        }
+        if collapsed {
+            if self.summary_right {
+                columns.get_mut(last + 1).collapsed = true;
+            } else if first > 0 {
+                columns.get_mut(first - 1).collapsed = true;
+            }
+        }
+    }
+
+    fn add_row_group(
+        &mut self,
+        first: WorksheetRow,
+        last: WorksheetRow,
+        level: u8,
+        collapsed: bool,
+    ) {
+        for row in first..=last {
+            let setting = self.rows.entry(row).or_default();
+            setting.level = setting.level.max(level);
+            setting.hidden |= collapsed;
+        }
+        if collapsed {
+            if self.summary_below {
+                self.rows.entry(last + 1).or_default().collapsed = true;
+            } else if first > 0 {
+                self.rows.entry(first - 1).or_default().collapsed = true;
+            }
+        }
+    }
+
+    pub fn add_sheet_groups(
+        &mut self,
+        worksheet_def: &WorksheetDef,
+        columns: &mut ColumnSettings,
+    ) -> anyhow::Result<()> {
+        for group in worksheet_def.row_groups.iter() {
+            let first = resolve_position(group.first.as_ref(), &[], 0)?;
+            let last = resolve_position(group.last.as_ref(), &[], first)?;
+            check_range(group, first, last)?;
+            self.add_row_group(first, last, group.level, group.collapsed);
+        }
+        for group in worksheet_def.column_groups.iter() {
+            let first = resolve_position(group.first.as_ref(), &[], 0)?;
+            let last = resolve_position(group.last.as_ref(), &[], first)?;
+            check_range(group, first, last)?;
+            self.add_column_group(
+                columns,
+                first as WorksheetCol,
+                last as WorksheetCol,
+                group.level,
+                group.collapsed,
+            );
+        }
+        Ok(())
+    }
+
+    pub fn add_source_groups(
+        &mut self,
+        source_def: &SheetSourceDef,
+        inserted: &InsertedSource,
+        columns: &mut ColumnSettings,
+    ) -> anyhow::Result<()> {
+        let extent = &inserted.extent;
+        for group in source_def.row_groups.iter() {
+            let first = resolve_position(group.first.as_ref(), &[], 1)?;
+            let last =
+                resolve_position(group.last.as_ref(), &[], extent.last_row - extent.first_row)?;
+            check_range(group, first, last)?;
+            self.add_row_group(
+                extent.first_row + first,
+                extent.first_row + last,
+                group.level,
+                group.collapsed,
+            );
+        }
+        for group in source_def.column_groups.iter() {
+            let first = resolve_position(group.first.as_ref(), &inserted.header, 0)?;
+            let last = resolve_position(
+                group.last.as_ref(),
+                &inserted.header,
+                (extent.last_col - extent.first_col) as u32,
+            )?;
+            check_range(group, first, last)?;
+            self.add_column_group(
+                columns,
+                extent.first_col + first as WorksheetCol,
+                extent.first_col + last as WorksheetCol,
+                group.level,
+                group.collapsed,
+            );
+        }
+        if let Some(vcf_config) = source_def.vcf_config.as_ref() {
+            if vcf_config.group_genotypes {
+                // The first column of each sample is kept visible as its summary
+                for (first, last) in inserted.sample_columns.iter().filter(|x| x.0 < x.1) {
+                    self.add_column_group(
+                        columns,
+                        extent.first_col + *first as WorksheetCol + 1,
+                        extent.first_col + *last as WorksheetCol,
+                        1,
+                        vcf_config.collapse_genotypes,
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply_settings(&self, worksheet: &mut Worksheet) {
+        if !self.summary_below || !self.summary_right {
+            worksheet.outline_settings(true, self.summary_below, self.summary_right, false);
+        }
+    }
+
+    pub fn apply_rows(&self, worksheet: &mut Worksheet, row_heights: &[f64]) -> anyhow::Result<()> {
+        for (row, setting) in self.rows.iter() {
+            worksheet.set_row_opt(
+                *row,
+                row_heights
+                    .get(*row as usize)
+                    .copied()
+                    .unwrap_or(LXW_DEF_ROW_HEIGHT),
+                None,
+                &RowColOptions::new(setting.hidden, setting.level, setting.collapsed),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::autofit::ColumnWidths;
+    use super::super::SourceExtent;
+    use super::*;
+
+    #[test]
+    fn test_row_groups() -> anyhow::Result<()> {
+        let worksheet_def: WorksheetDef = serde_json::from_value(serde_json::json!({
+            "row-groups": [
+                {"first": 2, "last": 4, "collapsed": true},
+                {"first": 3, "last": 3, "level": 2}
+            ]
+        }))?;
+        let mut outline = Outline::new(None);
+        outline.add_sheet_groups(&worksheet_def, &mut ColumnSettings::new())?;
+        assert_eq!(
+            outline
+                .rows
+                .iter()
+                .map(|(x, y)| (*x, *y))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    2,
+                    RowSetting {
+                        level: 1,
+                        hidden: true,
+                        collapsed: false
+                    }
+                ),
+                (
+                    3,
+                    RowSetting {
+                        level: 2,
+                        hidden: true,
+                        collapsed: false
+                    }
+                ),
+                (
+                    4,
+                    RowSetting {
+                        level: 1,
+                        hidden: true,
+                        collapsed: false
+                    }
+                ),
+                (
+                    5,
+                    RowSetting {
+                        level: 0,
+                        hidden: false,
+                        collapsed: true
+                    }
+                ),
+            ]
+        );
+
+        let worksheet_def: WorksheetDef = serde_json::from_value(serde_json::json!({
+            "row-groups": [{"first": 2, "level": 8}]
+        }))?;
+        assert!(Outline::new(None)
+            .add_sheet_groups(&worksheet_def, &mut ColumnSettings::new())
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_groups() -> anyhow::Result<()> {
+        let source_def: SheetSourceDef = serde_json::from_value(serde_json::json!({
+            "file": "sample.vcf",
+            "vcf-config": {"group-genotypes": true},
+            "row-groups": [{}],
+            "column-groups": [{"first": "QUAL", "last": "FILTER"}]
+        }))?;
+        let inserted = InsertedSource {
+            extent: SourceExtent {
+                first_row: 3,
+                first_col: 1,
+                last_row: 10,
+                last_col: 10,
+            },
+            data_rows: 7,
+            header: ["#", "CHROM", "POS", "QUAL", "FILTER"]
+                .iter()
+                .map(|x| x.to_string())
+                .collect(),
+            sample_columns: vec![(5, 7), (8, 8), (9, 10)],
+        };
+        let mut outline = Outline::new(None);
+        let mut columns = ColumnSettings::new();
+        outline.add_source_groups(&source_def, &inserted, &mut columns)?;
+        assert_eq!(
+            outline.rows.keys().copied().collect::<Vec<_>>(),
+            (4..=10).collect::<Vec<_>>()
+        );
+        let grouped: Vec<_> = (0..12).filter(|x| columns.get_mut(*x).level > 0).collect();
+        assert_eq!(grouped, vec![4, 5, 7, 8, 11]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_genotypes_widths() -> anyhow::Result<()> {
+        let inserted = InsertedSource {
+            extent: SourceExtent {
+                first_row: 0,
+                first_col: 0,
+                last_row: 10,
+                last_col: 9,
+            },
+            data_rows: 10,
+            header: Vec::new(),
+            sample_columns: vec![(4, 6), (7, 9)],
+        };
+        let mut widths = ColumnWidths::new();
+        for column in 0..=9 {
+            widths.record_source_width(column, 8. + column as f64);
+        }
+        let column_widths = |group_genotypes: bool| -> anyhow::Result<Vec<f64>> {
+            let source_def: SheetSourceDef = serde_json::from_value(serde_json::json!({
+                "file": "sample.vcf",
+                "vcf-config": {"group-genotypes": group_genotypes, "collapse-genotypes": true}
+            }))?;
+            let mut columns = ColumnSettings::new();
+            Outline::new(None).add_source_groups(&source_def, &inserted, &mut columns)?;
+            Ok((0..=10).map(|x| columns.width(x, &widths)).collect())
+        };
+        assert_eq!(column_widths(true)?, column_widths(false)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use xlsxwriter::worksheet::{Worksheet, WorksheetRow};
+use xlsxwriter::Workbook;
+
+pub const XLSX_MAX_ROW: WorksheetRow = 1048576;
+const SHEET_NAME_MAX_LENGTH: usize = 31;
+
+pub struct OverflowSheets<'b> {
+    workbook: &'b Workbook,
+    sheet_name: String,
+    sheets: Vec<Worksheet<'b>>,
+}
+
+impl<'b> OverflowSheets<'b> {
+    pub fn new(workbook: &'b Workbook, sheet_name: &str) -> Self {
+        OverflowSheets {
+            workbook,
+            sheet_name: sheet_name.to_string(),
+            sheets: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.sheets.len()
+    }
+
+    pub fn add_sheet(&mut self) -> anyhow::Result<&mut Worksheet<'b>> {
+        let name = continuation_sheet_name(&self.sheet_name, self.sheets.len() + 2);
+        let sheet = self.workbook.add_worksheet(Some(&name))?;
+        self.sheets.push(sheet);
+        Ok(self.sheets.last_mut().unwrap())
+    }
+
+    pub fn last_mut(&mut self) -> Option<&mut Worksheet<'b>> {
+        self.sheets.last_mut()
+    }
+
+    pub fn sheets_mut(&mut self) -> &mut [Worksheet<'b>] {
+        &mut self.sheets
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        (0..self.sheets.len())
+            .map(|i| continuation_sheet_name(&self.sheet_name, i + 2))
+            .collect()
+    }
+}
+
+pub fn continuation_sheet_name(sheet_name: &str, number: usize) -> String {
+    let suffix = format!(" ({})", number);
+    let base: String = sheet_name
+        .chars()
+        .take(SHEET_NAME_MAX_LENGTH - suffix.chars().count())
+        .collect();
+    format!("{}{}", base, suffix)
+}
+
+pub fn row_limit_error() -> anyhow::Error {
+    anyhow::anyhow!(
+        "The number of rows exceeds the limit of Excel ({} rows)",
+        XLSX_MAX_ROW
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_continuation_sheet_name() {
+        assert_eq!(continuation_sheet_name("Variants", 2), "Variants (2)");
+        assert_eq!(
+            continuation_sheet_name("A very long sheet name for variants", 12),
+            "A very long sheet name for (12)"
+        );
+    }
+}
@@ -0,0 +1,375 @@
+use crate::model::*;
+use anyhow::Context;
+use encoding_rs::Encoding;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use regex::Regex;
+use std::io::{BufRead, Read};
+use std::path::{Path, PathBuf};
+
+pub struct SourceInput<'a> {
+    pub name: String,
+    pub path: Option<PathBuf>,
+    pub data: Option<&'a str>,
+}
+
+impl<'a> SourceInput<'a> {
+    pub fn open(&self) -> anyhow::Result<Box<dyn BufRead + 'a>> {
+        if let Some(path) = self.path.as_ref() {
+            Ok(Box::new(std::io::BufReader::new(
+                autocompress::autodetect_open(path)
+                    .with_context(|| format!("Cannot open \"{}\"", self.name))?,
+            )))
+        } else if let Some(data) = self.data {
+            Ok(Box::new(data.as_bytes()))
+        } else {
+            unreachable!()
+        }
+    }
+
+    pub fn open_text(
+        &self,
+        encoding: Option<&'static Encoding>,
+    ) -> anyhow::Result<Box<dyn BufRead + 'a>> {
+        Ok(Box::new(std::io::BufReader::new(
+            DecodeReaderBytesBuilder::new()
+                .encoding(encoding)
+                .bom_override(true)
+                .utf8_passthru(true)
+                .strip_bom(true)
+                .build(self.open()?),
+        )))
+    }
+
+    pub fn label(&self, pattern: Option<&Regex>) -> String {
+        let file_name = self
+            .path
+            .as_ref()
+            .and_then(|x| x.file_name())
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.name.to_string());
+        if let Some(captures) = pattern.and_then(|x| x.captures(&file_name)) {
+            captures
+                .get(1)
+                .or_else(|| captures.get(0))
+                .map(|x| x.as_str().to_string())
+                .unwrap_or(file_name)
+        } else {
+            file_name
+        }
+    }
+}
+
+pub type RecordIter<'a> = Box<dyn Iterator<Item = anyhow::Result<csv::StringRecord>> + 'a>;
+
+pub struct TextSourceReader {
+    encoding: Option<&'static Encoding>,
+    reader_builder: csv::ReaderBuilder,
+    skip_lines: usize,
+    skip_until: Option<Regex>,
+    header_rows: usize,
+    merge_header_rows: bool,
+}
+
+impl TextSourceReader {
+    pub fn new(source_def: &SheetSourceDef, inputs: &[SourceInput]) -> anyhow::Result<Self> {
+        let encoding = source_def
+            .encoding
+            .as_deref()
+            .map(|x| {
+                Encoding::for_label(x.as_bytes())
+                    .ok_or_else(|| anyhow::anyhow!("Unknown encoding: {}", x))
+            })
+            .transpose()?;
+        let skip_until = source_def
+            .skip_until
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .context("Invalid skip-until pattern")?;
+        let header_rows = if source_def.has_header {
+            source_def.header_rows.unwrap_or(1)
+        } else {
+            0
+        };
+        if source_def.has_header && header_rows == 0 {
+            return Err(anyhow::anyhow!("header-rows must be 1 or more"));
+        }
+
+        let mut reader_builder = csv::ReaderBuilder::new();
+        reader_builder.has_headers(false);
+        reader_builder.flexible(true);
+        let mut comment_line_prefix_byte = None;
+        if let Some(comment_line_prefix) = source_def.comment_line_prefix.as_deref() {
+            if comment_line_prefix.len() == 1 {
+                comment_line_prefix_byte = Some(comment_line_prefix.as_bytes()[0]);
+                reader_builder.comment(comment_line_prefix_byte);
+            } else {
+                return Err(anyhow::anyhow!("Comment line prefix must be length 1"));
+            }
+        }
+
+        let mut reader = TextSourceReader {
+            encoding,
+            reader_builder,
+            skip_lines: source_def.skip_lines.unwrap_or(0),
+            skip_until,
+            header_rows,
+            merge_header_rows: source_def.merge_header_rows,
+        };
+
+        let (default_delimiter, default_quoting) = match source_def.suggest_format() {
+            SheetSourceType::CSV => (b',', true),
+            SheetSourceType::TSV => (b'\t', false),
+            _ => unreachable!(),
+        };
+        let delimiter = match source_def.delimiter {
+            None => default_delimiter,
+            Some(SourceDelimiter::Comma) => b',',
+            Some(SourceDelimiter::Tab) => b'\t',
+            Some(SourceDelimiter::Semicolon) => b';',
+            Some(SourceDelimiter::Pipe) => b'|',
+            Some(SourceDelimiter::Auto) => match inputs.first() {
+                Some(input) => sniff_delimiter(reader.open(input)?, comment_line_prefix_byte)?
+                    .unwrap_or(default_delimiter),
+                None => default_delimiter,
+            },
+        };
+        let quoting = source_def.quoting.unwrap_or(default_quoting);
+        reader
+            .reader_builder
+            .delimiter(delimiter)
+            .quoting(quoting)
+            .double_quote(quoting);
+        Ok(reader)
+    }
+
+    pub fn header_records(&self) -> usize {
+        if self.merge_header_rows {
+            self.header_rows.min(1)
+        } else {
+            self.header_rows
+        }
+    }
+
+    pub fn open<'a>(&self, input: &SourceInput<'a>) -> anyhow::Result<Box<dyn BufRead + 'a>> {
+        let mut reader = input.open_text(self.encoding)?;
+        let mut line = String::new();
+        for _ in 0..self.skip_lines {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+        }
+        if let Some(pattern) = self.skip_until.as_ref() {
+            loop {
+                line.clear();
+                if reader.read_line(&mut line)? == 0 {
+                    return Err(anyhow::anyhow!(
+                        "No line matches to skip-until pattern in \"{}\"",
+                        input.name
+                    ));
+                }
+                if pattern.is_match(line.trim_end_matches(['\r', '\n'])) {
+                    return Ok(Box::new(std::io::Cursor::new(line).chain(reader)));
+                }
+            }
+        }
+        Ok(reader)
+    }
+
+    pub fn records<'a>(&self, input: &SourceInput<'a>) -> anyhow::Result<RecordIter<'a>> {
+        let name = input.name.clone();
+        let mut records = self
+            .reader_builder
+            .from_reader(self.open(input)?)
+            .into_records()
+            .map(move |x| x.with_context(|| format!("Cannot read \"{}\"", name)));
+        if !self.merge_header_rows || self.header_rows <= 1 {
+            return Ok(Box::new(records));
+        }
+
+        let mut header: Vec<String> = Vec::new();
+        for row in records.by_ref().take(self.header_rows) {
+            for (j, cell) in row?.iter().enumerate() {
+                if header.len() <= j {
+                    header.resize(j + 1, String::new());
+                }
+                if !cell.is_empty() {
+                    if !header[j].is_empty() {
+                        header[j].push(' ');
+                    }
+                    header[j].push_str(cell);
+                }
+            }
+        }
+        Ok(Box::new(
+            std::iter::once(Ok(csv::StringRecord::from(header))).chain(records),
+        ))
+    }
+}
+
+fn is_glob_pattern(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+pub fn source_inputs<'a, P: AsRef<Path>>(
+    source_def: &'a SheetSourceDef,
+    base_path: P,
+) -> anyhow::Result<Vec<SourceInput<'a>>> {
+    if let Some(file) = source_def.file.as_ref() {
+        let mut inputs = Vec::new();
+        for pattern in file.patterns() {
+            if is_glob_pattern(pattern) {
+                let full_pattern = base_path.as_ref().join(pattern);
+                let mut matched = glob::glob(&full_pattern.to_string_lossy())
+                    .with_context(|| format!("Invalid glob pattern \"{}\"", pattern))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                if matched.is_empty() {
+                    return Err(anyhow::anyhow!("No file matched to \"{}\"", pattern));
+                }
+                matched.sort();
+                for path in matched {
+                    inputs.push(SourceInput {
+                        name: path
+                            .strip_prefix(base_path.as_ref())
+                            .unwrap_or(&path)
+                            .to_string_lossy()
+                            .to_string(),
+                        path: Some(path),
+                        data: None,
+                    });
+                }
+            } else {
+                inputs.push(SourceInput {
+                    name: pattern.to_string(),
+                    path: Some(base_path.as_ref().join(pattern)),
+                    data: None,
+                });
+            }
+        }
+        Ok(inputs)
+    } else if let Some(data) = source_def.data.as_deref() {
+        Ok(vec![SourceInput {
+            name: "embedded data".to_string(),
+            path: None,
+            data: Some(data),
+        }])
+    } else {
+        Ok(vec![])
+    }
+}
+
+pub fn sniff_delimiter<R: BufRead>(reader: R, comment: Option<u8>) -> anyhow::Result<Option<u8>> {
+    const CANDIDATES: [u8; 4] = [b',', b'\t', b';', b'|'];
+    let mut counts: Vec<[usize; 4]> = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.is_empty() || comment.map(|x| line.as_bytes()[0] == x).unwrap_or(false) {
+            continue;
+        }
+        let mut count = [0; 4];
+        for (i, candidate) in CANDIDATES.iter().enumerate() {
+            count[i] = line.bytes().filter(|x| x == candidate).count();
+        }
+        counts.push(count);
+        if counts.len() >= 10 {
+            break;
+        }
+    }
+
+    Ok((0..CANDIDATES.len())
+        .filter(|i| counts.iter().all(|x| x[*i] > 0 && x[*i] == counts[0][*i]))
+        .max_by_key(|i| counts.first().map(|x| x[*i]).unwrap_or(0))
+        .or_else(|| {
+            (0..CANDIDATES.len())
+                .filter(|i| counts.iter().any(|x| x[*i] > 0))
+                .max_by_key(|i| counts.iter().map(|x| x[*i]).sum::<usize>())
+        })
+        .map(|i| CANDIDATES[i]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_source_label() -> anyhow::Result<()> {
+        let input = SourceInput {
+            name: "results/sample12.tsv".to_string(),
+            path: Some(PathBuf::from("results/sample12.tsv")),
+            data: None,
+        };
+        assert_eq!(input.label(None), "sample12.tsv");
+        let pattern = Regex::new(r"^(sample\d+)\.tsv$")?;
+        assert_eq!(input.label(Some(&pattern)), "sample12");
+        let pattern = Regex::new(r"\d+")?;
+        assert_eq!(input.label(Some(&pattern)), "12");
+        let pattern = Regex::new(r"^control")?;
+        assert_eq!(input.label(Some(&pattern)), "sample12.tsv");
+        Ok(())
+    }
+
+    #[test]
+    fn test_sniff_delimiter() -> anyhow::Result<()> {
+        assert_eq!(sniff_delimiter(&b"a;b;c\n1;2,5;3\n"[..], None)?, Some(b';'));
+        assert_eq!(sniff_delimiter(&b"a\tb\n1\t2\n"[..], None)?, Some(b'\t'));
+        assert_eq!(
+            sniff_delimiter(&b"# x,y,z\na|b\n1|2\n"[..], Some(b'#'))?,
+            Some(b'|')
+        );
+        assert_eq!(sniff_delimiter(&b"a\nb\n"[..], None)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_text() -> anyhow::Result<()> {
+        let read_all = |path: &str, encoding| -> anyhow::Result<String> {
+            let input = SourceInput {
+                name: path.to_string(),
+                path: Some(PathBuf::from(path)),
+                data: None,
+            };
+            let mut text = String::new();
+            input.open_text(encoding)?.read_to_string(&mut text)?;
+            Ok(text)
+        };
+        let expected = "Gene\tNote\nTP53\t変異あり\n";
+        assert_eq!(read_all("examples/data/utf16.tsv", None)?, expected);
+        assert_eq!(
+            read_all("examples/data/utf16.tsv", Encoding::for_label(b"shift_jis"))?,
+            expected
+        );
+        assert_eq!(
+            read_all(
+                "examples/data/shift_jis.tsv",
+                Encoding::for_label(b"shift_jis")
+            )?,
+            expected
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_source_reader() -> anyhow::Result<()> {
+        let source_def: SheetSourceDef = serde_json::from_value(serde_json::json!({
+            "file": "data/instrument.csv",
+            "skip-until": "^Well,",
+            "header-rows": 2,
+            "merge-header-rows": true
+        }))?;
+        let inputs = source_inputs(&source_def, "examples")?;
+        let reader = TextSourceReader::new(&source_def, &inputs)?;
+        assert_eq!(reader.header_records(), 1);
+        let records = reader
+            .records(&inputs[0])?
+            .map(|x| x.map(|y| y.iter().map(|z| z.to_string()).collect::<Vec<_>>()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(
+            records[0],
+            vec!["Well", "Sample", "Concentration ng/uL", "Volume uL"]
+        );
+        assert_eq!(records[1], vec!["A1", "S1", "12.5", "20"]);
+        assert_eq!(records.len(), 5);
+        Ok(())
+    }
+}
@@ -1,9 +1,10 @@
 use xlsxwriter::worksheet::table::{TableColumn, TableOptions, TableStyleType, TableTotalFunction};
-use xlsxwriter::worksheet::{Worksheet, WorksheetCol, WorksheetRow};
+use xlsxwriter::worksheet::{Worksheet, WorksheetCol};
 
 use crate::model::SheetSourceDef;
 
 use super::format::FormatManager;
+use super::SourceExtent;
 
 pub fn setup_table(
     worksheet: &mut Worksheet,
@@ -11,10 +12,9 @@ pub fn setup_table(
     _formats: &FormatManager,
     column_header: &[String],
     filter_col: Option<WorksheetCol>,
-    maximum_row: WorksheetRow,
-    maximum_col: WorksheetCol,
+    extent: &SourceExtent,
 ) -> anyhow::Result<()> {
-    if maximum_row <= 1 {
+    if extent.last_row - extent.first_row <= 1 {
         return Ok(());
     }
     if source_def.table {
@@ -41,23 +41,23 @@ pub fn setup_table(
             .unwrap_or(TableStyleType::Default);
         table_options.style_type_number = source_def.table_style_type_num.unwrap_or(0);
         worksheet.add_table(
-            source_def.start_row,
-            source_def.start_column,
-            source_def.start_row + maximum_row,
-            source_def.start_column + maximum_col,
+            extent.first_row,
+            extent.first_col,
+            extent.last_row,
+            extent.last_col,
             Some(table_options),
         )?;
     } else if source_def.autofilter {
         worksheet.autofilter(
-            source_def.start_row,
-            source_def.start_column,
-            source_def.start_row + maximum_row,
-            source_def.start_column + maximum_col,
+            extent.first_row,
+            extent.first_col,
+            extent.last_row,
+            extent.last_col,
         )?;
         if let Some(filter_list) = source_def.filter_list.as_ref() {
             if let Some(column_index) = filter_col {
                 let list: Vec<_> = filter_list.items.iter().map(|x| x.as_str()).collect();
-                worksheet.filter_list(column_index + source_def.start_column, &list)?;
+                worksheet.filter_list(column_index + extent.first_col, &list)?;
             }
         }
     }
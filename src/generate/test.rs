@@ -9,6 +9,7 @@ fn test_generate1() -> anyhow::Result<()> {
         "test1.xlsx",
         "examples",
         Some(load_list("examples/vcf/canonical.txt")?),
+        false,
     )?;
     Ok(())
 }
@@ -22,6 +23,296 @@ fn test_generate2() -> anyhow::Result<()> {
         "test2.xlsx",
         "examples",
         Some(load_list("examples/real-vcf/canonical.txt.xz")?),
+        false,
     )?;
     Ok(())
 }
+
+#[test]
+fn test_generate3() -> anyhow::Result<()> {
+    let json_data = include_bytes!("../../examples/test3.json");
+    let data: WorkbookDef = serde_json::from_reader(&json_data[..])?;
+    generate(&data, "test3.xlsx", "examples", None, false)?;
+    Ok(())
+}
+
+#[test]
+fn test_resolve_start_position() {
+    let previous = SourceExtent {
+        first_row: 2,
+        first_col: 1,
+        last_row: 10,
+        last_col: 4,
+    };
+    assert_eq!(
+        resolve_start_row(SourcePosition::Absolute(3), 1, &[previous]),
+        3
+    );
+    assert_eq!(
+        resolve_start_row(
+            SourcePosition::Relative(RelativePosition::AfterPrevious),
+            2,
+            &[previous]
+        ),
+        13
+    );
+    assert_eq!(
+        resolve_start_row(
+            SourcePosition::Relative(RelativePosition::SameAsPrevious),
+            2,
+            &[previous]
+        ),
+        2
+    );
+    assert_eq!(
+        resolve_start_row(
+            SourcePosition::Relative(RelativePosition::AfterPrevious),
+            2,
+            &[]
+        ),
+        0
+    );
+    assert_eq!(
+        resolve_start_column(
+            SourcePosition::Relative(RelativePosition::AfterPrevious),
+            1,
+            &[previous]
+        ),
+        6
+    );
+}
+
+#[test]
+fn test_resolve_start_position_after_side_by_side() {
+    // A is tall, B is placed right of A and is short
+    let extents = [
+        SourceExtent {
+            first_row: 0,
+            first_col: 0,
+            last_row: 20,
+            last_col: 3,
+        },
+        SourceExtent {
+            first_row: 0,
+            first_col: 5,
+            last_row: 4,
+            last_col: 7,
+        },
+    ];
+    assert_eq!(
+        resolve_start_row(
+            SourcePosition::Relative(RelativePosition::AfterPrevious),
+            1,
+            &extents
+        ),
+        22
+    );
+    assert_eq!(
+        resolve_start_row(
+            SourcePosition::Relative(RelativePosition::SameAsPrevious),
+            1,
+            &extents
+        ),
+        0
+    );
+    assert_eq!(
+        resolve_start_column(
+            SourcePosition::Relative(RelativePosition::SameAsPrevious),
+            1,
+            &extents
+        ),
+        5
+    );
+}
+
+#[test]
+fn test_generate_constant_memory() -> anyhow::Result<()> {
+    let mut data: WorkbookDef = serde_json::from_value(serde_json::json!({
+        "constant-memory": true,
+        "sheets": [{
+            "name": "Samples",
+            "source": [{"file": "data/samples/sample1.tsv", "start-row": 2}],
+            "cells": [
+                {"row": 0, "column": 0, "value": "Title"},
+                {"anchor": {"position": "below"}, "row-relative": 1, "value": "Footer"}
+            ]
+        }]
+    }))?;
+    generate(&data, "test-constant-memory.xlsx", "examples", None, false)?;
+
+    data.sheets[0].cells.push(CellDef {
+        row: Some(3),
+        column: Some(0),
+        row_relative: None,
+        column_relative: None,
+        format: None,
+        value: Some(CellValue::String("Inside".to_string())),
+        cell_type: CellType::Auto,
+        comment: None,
+        url: None,
+        link_to: None,
+        merge_row: None,
+        merge_column: None,
+        anchor: None,
+    });
+    assert!(generate(&data, "test-constant-memory.xlsx", "examples", None, false).is_err());
+    data.sheets[0].cells.pop();
+
+    // The back link is written first, so nothing may be placed above it
+    data.contents = Some(serde_json::from_value(
+        serde_json::json!({"back-link-cell": "B1"}),
+    )?);
+    generate(&data, "test-constant-memory.xlsx", "examples", None, false)?;
+    data.contents = Some(serde_json::from_value(
+        serde_json::json!({"back-link-cell": "B2"}),
+    )?);
+    assert!(generate(&data, "test-constant-memory.xlsx", "examples", None, false).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_generate_internal_links() -> anyhow::Result<()> {
+    let mut data: WorkbookDef = serde_json::from_value(serde_json::json!({
+        "contents": {"name": "Index", "back-link-cell": "E1"},
+        "sheets": [
+            {
+                "name": "Summary",
+                "cells": [{"row": 0, "column": 0, "link-to": {"sheet": "Samples"}}]
+            },
+            {
+                "name": "Samples",
+                "description": "Sample 1",
+                "source": [{"file": "data/samples/sample1.tsv"}],
+                "cells": [{"row": 0, "column": 5, "value": "Top", "link-to": {"sheet": "Index"}}]
+            }
+        ]
+    }))?;
+    generate(&data, "test-internal-links.xlsx", "examples", None, false)?;
+
+    data.sheets[0].cells[0].link_to = Some(LinkToDef {
+        sheet: "Variants".to_string(),
+        cell: None,
+    });
+    assert!(generate(&data, "test-internal-links.xlsx", "examples", None, false).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_generate_internal_links_to_generated_sheets() -> anyhow::Result<()> {
+    let mut data: WorkbookDef = serde_json::from_value(serde_json::json!({
+        "contents": {"back-link-cell": "A1"},
+        "sheets": [
+            {
+                "name": "Summary",
+                "cells": [{"row": 1, "column": 0, "link-to": {"sheet": "Variants header"}}]
+            },
+            {
+                "name": "Variants",
+                "source": [{
+                    "file": "vcf/simple1.vcf",
+                    "start-row": 1,
+                    "vcf-config": {"metadata-sheet": "Variants header"}
+                }]
+            }
+        ]
+    }))?;
+    generate(
+        &data,
+        "test-internal-links-generated.xlsx",
+        "examples",
+        None,
+        false,
+    )?;
+
+    // A link to a continuation sheet is accepted only for sources that may overflow
+    data.sheets[0].cells[0].link_to = Some(LinkToDef {
+        sheet: "Variants (2)".to_string(),
+        cell: None,
+    });
+    assert!(generate(
+        &data,
+        "test-internal-links-generated.xlsx",
+        "examples",
+        None,
+        false
+    )
+    .is_err());
+    if let Some(SheetSource::Def(sources)) = data.sheets[1].source.as_mut() {
+        sources[0].overflow = OverflowPolicy::NewSheet;
+    }
+    let error = generate(
+        &data,
+        "test-internal-links-generated.xlsx",
+        "examples",
+        None,
+        false,
+    )
+    .unwrap_err()
+    .to_string();
+    assert_eq!(
+        error,
+        "Link target sheet \"Variants (2)\" was not created because the table did not overflow"
+    );
+
+    // The back link cell is reserved
+    data.sheets[0].cells[0].link_to = None;
+    if let Some(SheetSource::Def(sources)) = data.sheets[1].source.as_mut() {
+        sources[0].start_row = SourcePosition::Absolute(0);
+    }
+    assert!(generate(
+        &data,
+        "test-internal-links-generated.xlsx",
+        "examples",
+        None,
+        false
+    )
+    .is_err());
+    if let Some(SheetSource::Def(sources)) = data.sheets[1].source.as_mut() {
+        sources[0].start_row = SourcePosition::Absolute(1);
+    }
+    generate(
+        &data,
+        "test-internal-links-generated.xlsx",
+        "examples",
+        None,
+        false,
+    )?;
+    data.sheets[0].cells[0].row = Some(0);
+    assert!(generate(
+        &data,
+        "test-internal-links-generated.xlsx",
+        "examples",
+        None,
+        false
+    )
+    .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_generate_merge_samples() -> anyhow::Result<()> {
+    let mut data: WorkbookDef = serde_json::from_value(serde_json::json!({
+        "sheets": [{
+            "name": "Families",
+            "source": [{
+                "file": ["vcf/family-a.vcf", "vcf/family-b.vcf"],
+                "vcf-config": {
+                    "format": ["GT"],
+                    "samples": ["SRP150637__HG00099", "SRP150637__HG00104"]
+                }
+            }]
+        }]
+    }))?;
+    generate(&data, "test-merge-samples.xlsx", "examples", None, false)?;
+
+    if let Some(SheetSource::Def(sources)) = data.sheets[0].source.as_mut() {
+        sources[0].vcf_config.as_mut().unwrap().samples =
+            Some(SamplesDef::List(vec!["SRP150637__HG00001".to_string()]));
+    }
+    let error = generate(&data, "test-merge-samples.xlsx", "examples", None, false).unwrap_err();
+    assert_eq!(
+        error.root_cause().to_string(),
+        "Sample \"SRP150637__HG00001\" is not found in VCF"
+    );
+    Ok(())
+}
@@ -0,0 +1,445 @@
+use super::SnpEffImpact;
+use crate::model::AnnotationTranscript;
+use anyhow::Result;
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::str;
+use vcf::{U8Vec, VCFHeader, VCFRecord};
+
+pub const SNPEFF_FIELDS: &[&str] = &[
+    "Allele",
+    "Annotation",
+    "Annotation_Impact",
+    "Gene_Name",
+    "Gene_ID",
+    "Feature_Type",
+    "Feature_ID",
+    "Transcript_BioType",
+    "Rank",
+    "HGVS.c",
+    "HGVS.p",
+    "cDNA.pos / cDNA.length",
+    "CDS.pos / CDS.length",
+    "AA.pos / AA.length",
+    "Distance",
+    "ERRORS / WARNINGS / INFO",
+];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AnnotationKind {
+    SnpEff,
+    Vep,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AnnotationLayout {
+    pub kind: AnnotationKind,
+    pub key: U8Vec,
+    pub fields: Vec<String>,
+    allele: usize,
+    allele_num: Option<usize>,
+    impact: usize,
+    symbol: Option<usize>,
+    gene: Option<usize>,
+    transcript: Option<usize>,
+    hgvs_c: Option<usize>,
+    hgvs_p: Option<usize>,
+    canonical: Option<usize>,
+    mane: Option<usize>,
+}
+
+/// Part of a sub-field such as `cDNA.pos / cDNA.length`
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub enum FieldPart {
+    Whole,
+    Position,
+    Length,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Annotation<'a> {
+    pub raw: &'a [u8],
+    pub fields: Vec<&'a [u8]>,
+}
+
+impl<'a> Annotation<'a> {
+    pub fn field(&self, index: Option<usize>) -> &'a [u8] {
+        index
+            .and_then(|x| self.fields.get(x))
+            .copied()
+            .unwrap_or(b"")
+    }
+}
+
+fn strip_feature_prefix(kind: AnnotationKind, value: &[u8]) -> &[u8] {
+    // VEP prefixes HGVS notations with the feature ID, e.g. ENST00000380152.8:c.-26G>A
+    match value.iter().position(|x| *x == b':') {
+        Some(x) if kind == AnnotationKind::Vep => &value[x + 1..],
+        _ => value,
+    }
+}
+
+pub fn vep_allele(record: &VCFRecord, alt_index: usize) -> U8Vec {
+    let alt = &record.alternative[alt_index];
+    let first = record.reference.first();
+    let same_first_base = first.is_some()
+        && record
+            .alternative
+            .iter()
+            .all(|x| x.first() == first && !x.starts_with(b"<"));
+    let same_length = record
+        .alternative
+        .iter()
+        .all(|x| x.len() == record.reference.len());
+    if same_first_base && !same_length {
+        if alt.len() == 1 {
+            b"-".to_vec()
+        } else {
+            alt[1..].to_vec()
+        }
+    } else {
+        alt.to_vec()
+    }
+}
+
+impl AnnotationLayout {
+    pub fn snpeff() -> Self {
+        AnnotationLayout {
+            kind: AnnotationKind::SnpEff,
+            key: b"ANN".to_vec(),
+            fields: SNPEFF_FIELDS.iter().map(|x| x.to_string()).collect(),
+            allele: 0,
+            allele_num: None,
+            impact: 2,
+            symbol: Some(3),
+            gene: Some(3),
+            transcript: Some(6),
+            hgvs_c: Some(9),
+            hgvs_p: Some(10),
+            canonical: None,
+            mane: None,
+        }
+    }
+
+    pub fn vep(description: &[u8]) -> Result<Self> {
+        let description = str::from_utf8(description)?;
+        let format = description
+            .split_once("Format:")
+            .map(|x| x.1)
+            .ok_or_else(|| anyhow::anyhow!("Field layout is not found in CSQ description"))?;
+        let fields: Vec<String> = format
+            .trim()
+            .trim_matches('"')
+            .split('|')
+            .map(|x| x.trim().to_string())
+            .collect();
+        let find = |name: &str| fields.iter().position(|x| x == name);
+        let required = |name: &str| {
+            find(name).ok_or_else(|| anyhow::anyhow!("{} is not found in CSQ field layout", name))
+        };
+        Ok(AnnotationLayout {
+            kind: AnnotationKind::Vep,
+            key: b"CSQ".to_vec(),
+            allele: required("Allele")?,
+            allele_num: find("ALLELE_NUM"),
+            impact: required("IMPACT")?,
+            symbol: find("SYMBOL"),
+            gene: find("Gene"),
+            transcript: find("Feature"),
+            hgvs_c: find("HGVSc"),
+            hgvs_p: find("HGVSp"),
+            canonical: find("CANONICAL"),
+            mane: find("MANE_SELECT").or_else(|| find("MANE")),
+            fields,
+        })
+    }
+
+    /// SnpEff's ANN is preferred when a VCF is annotated by both tools.
+    pub fn from_header(header: &VCFHeader) -> Option<Self> {
+        if header.info(b"ANN").is_some() {
+            return Some(Self::snpeff());
+        }
+        let info = header.info(b"CSQ")?;
+        match Self::vep(info.description) {
+            Ok(layout) => Some(layout),
+            Err(e) => {
+                eprintln!("WARNING: CSQ annotation is ignored: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Finds a sub-field by its name. `cDNA.pos` or `cDNA.length` selects a part of `cDNA.pos / cDNA.length`.
+    pub fn find_field(&self, name: &str) -> Option<(usize, FieldPart)> {
+        if let Some(index) = self.fields.iter().position(|x| x == name) {
+            return Some((index, FieldPart::Whole));
+        }
+        self.fields.iter().enumerate().find_map(|(index, field)| {
+            let parts: Vec<_> = field.split(" / ").collect();
+            match parts.iter().position(|x| x.eq_ignore_ascii_case(name)) {
+                Some(0) if parts.len() == 2 && name.ends_with(".pos") => {
+                    Some((index, FieldPart::Position))
+                }
+                Some(1) if parts.len() == 2 && name.ends_with(".length") => {
+                    Some((index, FieldPart::Length))
+                }
+                Some(0) => Some((index, FieldPart::Whole)),
+                _ => None,
+            }
+        })
+    }
+
+    pub fn field_value<'a>(
+        &self,
+        annotation: &Annotation<'a>,
+        index: usize,
+        part: FieldPart,
+    ) -> &'a [u8] {
+        let value = annotation.field(Some(index));
+        let mut parts = value.splitn(2, |x| *x == b'/');
+        match part {
+            FieldPart::Whole => value,
+            FieldPart::Position => parts.next().unwrap_or(b""),
+            FieldPart::Length => parts.nth(1).unwrap_or(b""),
+        }
+    }
+
+    /// Selects the most severe annotation, only from canonical transcripts if requested.
+    pub fn select<'a>(
+        &self,
+        record: &'a VCFRecord,
+        alt_index: Option<usize>,
+        transcript: AnnotationTranscript,
+        canonical_list: Option<&HashSet<U8Vec>>,
+    ) -> Result<Option<Annotation<'a>>> {
+        let mut selected: Option<(SnpEffImpact, Annotation)> = None;
+        for annotation in self.annotations(record, alt_index) {
+            if transcript == AnnotationTranscript::Canonical
+                && self.is_canonical(&annotation, canonical_list) != Some(true)
+            {
+                continue;
+            }
+            let impact = self.impact(&annotation)?;
+            if selected.as_ref().map(|x| impact < x.0).unwrap_or(true) {
+                selected = Some((impact, annotation));
+            }
+        }
+        Ok(selected.map(|x| x.1))
+    }
+
+    pub fn has_canonical_flags(&self) -> bool {
+        self.canonical.is_some() || self.mane.is_some()
+    }
+
+    pub fn parse<'a>(&self, record: &'a VCFRecord) -> Vec<Annotation<'a>> {
+        record
+            .info(&self.key)
+            .map(|values| {
+                values
+                    .iter()
+                    .map(|x| Annotation {
+                        raw: &x[..],
+                        fields: x.split(|y| *y == b'|').collect(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Parses annotations of a record and keeps the ones for the alternative allele if specified.
+    pub fn annotations<'a>(
+        &self,
+        record: &'a VCFRecord,
+        alt_index: Option<usize>,
+    ) -> Vec<Annotation<'a>> {
+        let mut annotations = self.parse(record);
+        if let Some(alt_index) = alt_index {
+            let allele = match self.kind {
+                AnnotationKind::SnpEff => record.alternative[alt_index].clone(),
+                AnnotationKind::Vep => vep_allele(record, alt_index),
+            };
+            let allele_num = (alt_index + 1).to_string();
+            annotations.retain(|x| match self.allele_num.map(|y| x.field(Some(y))) {
+                Some(num) if !num.is_empty() => num == allele_num.as_bytes(),
+                _ => x.field(Some(self.allele)) == &allele[..],
+            });
+        }
+        annotations
+    }
+
+    pub fn impact(&self, annotation: &Annotation) -> Result<SnpEffImpact> {
+        annotation.field(Some(self.impact)).try_into()
+    }
+
+    pub fn gene<'a>(&self, annotation: &Annotation<'a>) -> &'a [u8] {
+        let symbol = annotation.field(self.symbol);
+        if symbol.is_empty() {
+            annotation.field(self.gene)
+        } else {
+            symbol
+        }
+    }
+
+    pub fn transcript<'a>(&self, annotation: &Annotation<'a>) -> &'a [u8] {
+        annotation.field(self.transcript)
+    }
+
+    pub fn hgvs_c<'a>(&self, annotation: &Annotation<'a>) -> &'a [u8] {
+        strip_feature_prefix(self.kind, annotation.field(self.hgvs_c))
+    }
+
+    pub fn hgvs_p<'a>(&self, annotation: &Annotation<'a>) -> &'a [u8] {
+        strip_feature_prefix(self.kind, annotation.field(self.hgvs_p))
+    }
+
+    /// Protein change if available, otherwise coding change
+    pub fn change<'a>(&self, annotation: &Annotation<'a>) -> &'a [u8] {
+        let protein = self.hgvs_p(annotation);
+        if protein.is_empty() {
+            self.hgvs_c(annotation)
+        } else {
+            protein
+        }
+    }
+
+    /// Returns `None` if neither a canonical transcript list nor canonical flags of VEP are available.
+    pub fn is_canonical(
+        &self,
+        annotation: &Annotation,
+        canonical_list: Option<&HashSet<U8Vec>>,
+    ) -> Option<bool> {
+        if let Some(canonical_list) = canonical_list {
+            return Some(canonical_list.contains(self.transcript(annotation)));
+        }
+        if !self.has_canonical_flags() {
+            return None;
+        }
+        Some(annotation.field(self.canonical) == b"YES" || !annotation.field(self.mane).is_empty())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::BufReader;
+
+    const VEP_VCF: &[u8] = b"##fileformat=VCFv4.2
+##INFO=<ID=CSQ,Number=.,Type=String,Description=\"Consequence annotations from Ensembl VEP. Format: Allele|Consequence|IMPACT|SYMBOL|Gene|Feature|HGVSc|HGVSp|CANONICAL|MANE_SELECT\">
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+13\t100\t.\tGAA\tG,GAAA\t.\t.\tCSQ=-|frameshift_variant|HIGH|BRCA2|ENSG1|ENST1.8|ENST1.8:c.10del|ENSP1.3:p.Ile4fs|YES|NM_1.4,AAA|frameshift_variant|HIGH|BRCA2|ENSG1|ENST1.8|ENST1.8:c.10dup|ENSP1.3:p.Ile4fs|YES|NM_1.4,-|intron_variant|MODIFIER||ENSG1|ENST2.5|ENST2.5:c.5+1del|||
+";
+
+    #[test]
+    fn test_vep_annotations() -> Result<()> {
+        let mut data = BufReader::new(VEP_VCF);
+        let mut reader = vcf::VCFReader::new(&mut data)?;
+        let layout = AnnotationLayout::from_header(reader.header()).unwrap();
+        assert_eq!(layout.kind, AnnotationKind::Vep);
+        assert_eq!(layout.fields.len(), 10);
+
+        let mut record = VCFRecord::new(reader.header().clone());
+        assert!(reader.next_record(&mut record)?);
+        assert_eq!(vep_allele(&record, 0), b"-".to_vec());
+        assert_eq!(vep_allele(&record, 1), b"AAA".to_vec());
+        assert_eq!(layout.annotations(&record, None).len(), 3);
+
+        let deletion = layout.annotations(&record, Some(0));
+        assert_eq!(deletion.len(), 2);
+        assert_eq!(layout.impact(&deletion[0])?, SnpEffImpact::High);
+        assert_eq!(layout.gene(&deletion[0]), b"BRCA2");
+        assert_eq!(layout.gene(&deletion[1]), b"ENSG1");
+        assert_eq!(layout.hgvs_c(&deletion[0]), b"c.10del");
+        assert_eq!(layout.change(&deletion[0]), b"p.Ile4fs");
+        assert_eq!(layout.change(&deletion[1]), b"c.5+1del");
+        assert_eq!(layout.is_canonical(&deletion[0], None), Some(true));
+        assert_eq!(layout.is_canonical(&deletion[1], None), Some(false));
+
+        let canonical_list: HashSet<U8Vec> = [b"ENST2.5".to_vec()].iter().cloned().collect();
+        assert_eq!(
+            layout.is_canonical(&deletion[1], Some(&canonical_list)),
+            Some(true)
+        );
+        assert_eq!(layout.annotations(&record, Some(1)).len(), 1);
+
+        let selected = layout.select(&record, None, AnnotationTranscript::MostSevere, None)?;
+        assert_eq!(layout.hgvs_c(&selected.unwrap()), b"c.10del");
+        let selected = layout.select(
+            &record,
+            Some(0),
+            AnnotationTranscript::Canonical,
+            Some(&canonical_list),
+        )?;
+        assert_eq!(layout.transcript(&selected.unwrap()), b"ENST2.5");
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_field() {
+        let layout = AnnotationLayout::snpeff();
+        assert_eq!(layout.find_field("Gene_Name"), Some((3, FieldPart::Whole)));
+        assert_eq!(
+            layout.find_field("cDNA.pos"),
+            Some((11, FieldPart::Position))
+        );
+        assert_eq!(
+            layout.find_field("AA.length"),
+            Some((13, FieldPart::Length))
+        );
+        assert_eq!(layout.find_field("Errors"), Some((15, FieldPart::Whole)));
+        assert_eq!(layout.find_field("Unknown"), None);
+
+        let annotation = Annotation {
+            raw: b"",
+            fields: vec![
+                b"A",
+                b"",
+                b"",
+                b"",
+                b"",
+                b"",
+                b"",
+                b"",
+                b"",
+                b"",
+                b"",
+                b"123/4567",
+            ],
+        };
+        assert_eq!(
+            layout.field_value(&annotation, 11, FieldPart::Position),
+            b"123"
+        );
+        assert_eq!(
+            layout.field_value(&annotation, 11, FieldPart::Length),
+            b"4567"
+        );
+        assert_eq!(layout.field_value(&annotation, 12, FieldPart::Length), b"");
+    }
+
+    #[test]
+    fn test_vep_layout_error() {
+        assert!(AnnotationLayout::vep(b"Consequence annotations from Ensembl VEP").is_err());
+        assert!(AnnotationLayout::vep(b"Format: Allele|Consequence").is_err());
+    }
+
+    #[test]
+    fn test_vep_allele() -> Result<()> {
+        let vcf_data = b"##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+1\t10\t.\tA\tG\t.\t.\t.
+1\t20\t.\tAC\tGT\t.\t.\t.
+1\t30\t.\tA\tAT,<DEL>\t.\t.\t.
+";
+        let mut data = BufReader::new(&vcf_data[..]);
+        let mut reader = vcf::VCFReader::new(&mut data)?;
+        let mut record = VCFRecord::new(reader.header().clone());
+        let mut alleles = Vec::new();
+        while reader.next_record(&mut record)? {
+            for i in 0..record.alternative.len() {
+                alleles.push(String::from_utf8(vep_allele(&record, i))?);
+            }
+        }
+        assert_eq!(alleles, vec!["G", "GT", "AT", "<DEL>"]);
+        Ok(())
+    }
+}
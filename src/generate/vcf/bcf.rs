@@ -0,0 +1,483 @@
+use super::RecordSource;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read};
+use vcf::{U8Vec, VCFHeader, VCFRecord};
+
+const TYPE_INT8: u8 = 1;
+const TYPE_INT16: u8 = 2;
+const TYPE_INT32: u8 = 3;
+const TYPE_FLOAT: u8 = 5;
+const TYPE_CHAR: u8 = 7;
+
+const FLOAT_MISSING: u32 = 0x7F80_0001;
+const FLOAT_END_OF_VECTOR: u32 = 0x7F80_0002;
+
+/// Builds the contig and string dictionaries from BCF header text
+fn dictionaries(text: &[u8]) -> (Vec<U8Vec>, Vec<U8Vec>) {
+    fn attribute<'a>(line: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+        let start = (0..line.len()).find(|x| {
+            (line[*x] == b'<' || line[*x] == b',')
+                && line[x + 1..].starts_with(key)
+                && line.get(x + 1 + key.len()) == Some(&b'=')
+        })? + key.len()
+            + 2;
+        let value = &line[start..];
+        let end = value
+            .iter()
+            .position(|x| *x == b',' || *x == b'>')
+            .unwrap_or(value.len());
+        Some(&value[..end])
+    }
+
+    fn add(
+        dictionary: &mut HashMap<U8Vec, usize>,
+        next: &mut usize,
+        id: &[u8],
+        idx: Option<usize>,
+    ) {
+        if dictionary.contains_key(id) {
+            return;
+        }
+        let index = idx.unwrap_or(*next);
+        dictionary.insert(id.to_vec(), index);
+        *next = (*next).max(index + 1);
+    }
+
+    fn to_vec(dictionary: HashMap<U8Vec, usize>) -> Vec<U8Vec> {
+        let mut result = vec![Vec::new(); dictionary.values().max().map(|x| x + 1).unwrap_or(0)];
+        for (id, index) in dictionary {
+            result[index] = id;
+        }
+        result
+    }
+
+    let mut contigs = HashMap::new();
+    let mut next_contig = 0;
+    let mut strings = HashMap::new();
+    let mut next_string = 0;
+    add(&mut strings, &mut next_string, b"PASS", None);
+
+    for line in text.split(|x| *x == b'\n') {
+        let is_contig = line.starts_with(b"##contig=<");
+        if !is_contig
+            && !line.starts_with(b"##FILTER=<")
+            && !line.starts_with(b"##INFO=<")
+            && !line.starts_with(b"##FORMAT=<")
+        {
+            continue;
+        }
+        let id = match attribute(line, b"ID") {
+            Some(x) => x,
+            None => continue,
+        };
+        let idx = attribute(line, b"IDX")
+            .and_then(|x| std::str::from_utf8(x).ok())
+            .and_then(|x| x.parse().ok());
+        if is_contig {
+            add(&mut contigs, &mut next_contig, id, idx);
+        } else {
+            add(&mut strings, &mut next_string, id, idx);
+        }
+    }
+
+    (to_vec(contigs), to_vec(strings))
+}
+
+enum IntValue {
+    Value(i32),
+    Missing,
+    EndOfVector,
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8]> {
+        let end = self.position + length;
+        if end > self.data.len() {
+            return Err(anyhow::anyhow!("Truncated BCF record"));
+        }
+        let value = &self.data[self.position..end];
+        self.position = end;
+        Ok(value)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into()?))
+    }
+
+    /// Reads a typed value descriptor and returns its type and count
+    fn descriptor(&mut self) -> Result<(u8, usize)> {
+        let value = self.bytes(1)?[0];
+        let mut count = (value >> 4) as usize;
+        if count == 15 {
+            count = usize::try_from(self.typed_int()?).context("Invalid BCF vector length")?;
+        }
+        Ok((value & 0xf, count))
+    }
+
+    fn int(&mut self, value_type: u8) -> Result<IntValue> {
+        let (value, missing) = match value_type {
+            TYPE_INT8 => (self.bytes(1)?[0] as i8 as i32, i8::MIN as i32),
+            TYPE_INT16 => (
+                i16::from_le_bytes(self.bytes(2)?.try_into()?) as i32,
+                i16::MIN as i32,
+            ),
+            TYPE_INT32 => (self.i32()?, i32::MIN),
+            _ => return Err(anyhow::anyhow!("Unexpected BCF type: {}", value_type)),
+        };
+        Ok(if value == missing {
+            IntValue::Missing
+        } else if value == missing + 1 {
+            IntValue::EndOfVector
+        } else {
+            IntValue::Value(value)
+        })
+    }
+
+    fn typed_int(&mut self) -> Result<i32> {
+        let (value_type, count) = self.descriptor()?;
+        match (count, self.int(value_type)?) {
+            (1, IntValue::Value(x)) => Ok(x),
+            _ => Err(anyhow::anyhow!("Expected a single integer in BCF record")),
+        }
+    }
+
+    fn typed_string(&mut self) -> Result<&'a [u8]> {
+        let (value_type, count) = self.descriptor()?;
+        if value_type != TYPE_CHAR && count > 0 {
+            return Err(anyhow::anyhow!("Expected a string in BCF record"));
+        }
+        self.bytes(count)
+    }
+
+    /// Writes a vector of values in VCF text form
+    fn write_values(
+        &mut self,
+        value_type: u8,
+        count: usize,
+        genotype: bool,
+        line: &mut U8Vec,
+    ) -> Result<()> {
+        if value_type == TYPE_CHAR {
+            let value = self.bytes(count)?;
+            let end = value.iter().position(|x| *x == 0).unwrap_or(value.len());
+            if end == 0 {
+                line.push(b'.');
+            } else {
+                line.extend_from_slice(&value[..end]);
+            }
+            return Ok(());
+        }
+
+        let mut written = 0;
+        for _ in 0..count {
+            let value = if value_type == TYPE_FLOAT {
+                match self.u32()? {
+                    FLOAT_END_OF_VECTOR => continue,
+                    FLOAT_MISSING => None,
+                    x => Some(f32::from_bits(x).to_string()),
+                }
+            } else {
+                match self.int(value_type)? {
+                    IntValue::EndOfVector => continue,
+                    IntValue::Missing => None,
+                    IntValue::Value(x) if genotype => {
+                        if written > 0 {
+                            line.push(if x & 1 == 1 { b'|' } else { b'/' });
+                        }
+                        match x >> 1 {
+                            0 => line.push(b'.'),
+                            allele => line.extend_from_slice((allele - 1).to_string().as_bytes()),
+                        }
+                        written += 1;
+                        continue;
+                    }
+                    IntValue::Value(x) => Some(x.to_string()),
+                }
+            };
+            if written > 0 {
+                line.push(if genotype { b'/' } else { b',' });
+            }
+            match value {
+                Some(x) => line.extend_from_slice(x.as_bytes()),
+                None => line.push(b'.'),
+            }
+            written += 1;
+        }
+        if written == 0 {
+            line.push(b'.');
+        }
+        Ok(())
+    }
+}
+
+/// Reads records from a decompressed BCF stream
+pub struct BcfReader<R: Read> {
+    reader: R,
+    header: VCFHeader,
+    contigs: Vec<U8Vec>,
+    strings: Vec<U8Vec>,
+    shared: U8Vec,
+    individual: U8Vec,
+    line: U8Vec,
+    line_num: u64,
+}
+
+impl<R: Read> BcfReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut magic = [0; 5];
+        reader.read_exact(&mut magic).context("Cannot read BCF")?;
+        if magic[..4] != *b"BCF\x02" {
+            return Err(anyhow::anyhow!("Not a BCF version 2 file"));
+        }
+        let mut length = [0; 4];
+        reader.read_exact(&mut length)?;
+        let mut text = vec![0; u32::from_le_bytes(length) as usize];
+        reader.read_exact(&mut text)?;
+        while text.last() == Some(&0) {
+            text.pop();
+        }
+        let header = vcf::VCFReader::new(&text[..])?.header().clone();
+        let (contigs, strings) = dictionaries(&text);
+        Ok(BcfReader {
+            reader,
+            header,
+            contigs,
+            strings,
+            shared: Vec::new(),
+            individual: Vec::new(),
+            line: Vec::new(),
+            line_num: 0,
+        })
+    }
+
+    fn dictionary_value<'a>(dictionary: &'a [U8Vec], index: i32, kind: &str) -> Result<&'a [u8]> {
+        usize::try_from(index)
+            .ok()
+            .and_then(|x| dictionary.get(x))
+            .map(|x| &x[..])
+            .ok_or_else(|| anyhow::anyhow!("Unknown {} index in BCF record: {}", kind, index))
+    }
+
+    /// Converts the current record into a VCF text line
+    fn decode_line(&mut self) -> Result<()> {
+        let line = &mut self.line;
+        line.clear();
+        let mut shared = Cursor {
+            data: &self.shared,
+            position: 0,
+        };
+
+        let chrom = shared.i32()?;
+        line.extend_from_slice(Self::dictionary_value(&self.contigs, chrom, "contig")?);
+        line.push(b'\t');
+        line.extend_from_slice((shared.i32()? as i64 + 1).to_string().as_bytes());
+        line.push(b'\t');
+        // rlen
+        shared.i32()?;
+        let qual = shared.u32()?;
+        let n_info = shared.u16()?;
+        let n_allele = shared.u16()?;
+        let n_fmt_sample = shared.u32()?;
+        let n_sample = (n_fmt_sample & 0xff_ffff) as usize;
+        let n_fmt = (n_fmt_sample >> 24) as usize;
+
+        let id = shared.typed_string()?;
+        line.extend_from_slice(if id.is_empty() { b"." } else { id });
+        line.push(b'\t');
+        for i in 0..n_allele {
+            if i == 1 {
+                line.push(b'\t');
+            } else if i > 1 {
+                line.push(b',');
+            }
+            line.extend_from_slice(shared.typed_string()?);
+        }
+        if n_allele < 2 {
+            line.extend_from_slice(b"\t.");
+        }
+        line.push(b'\t');
+        if qual == FLOAT_MISSING {
+            line.push(b'.');
+        } else {
+            line.extend_from_slice(f32::from_bits(qual).to_string().as_bytes());
+        }
+        line.push(b'\t');
+
+        let (filter_type, filter_count) = shared.descriptor()?;
+        if filter_count == 0 {
+            line.push(b'.');
+        }
+        for i in 0..filter_count {
+            if i > 0 {
+                line.push(b';');
+            }
+            if let IntValue::Value(x) = shared.int(filter_type)? {
+                line.extend_from_slice(Self::dictionary_value(&self.strings, x, "FILTER")?);
+            }
+        }
+        line.push(b'\t');
+
+        if n_info == 0 {
+            line.push(b'.');
+        }
+        for i in 0..n_info {
+            if i > 0 {
+                line.push(b';');
+            }
+            let key = shared.typed_int()?;
+            line.extend_from_slice(Self::dictionary_value(&self.strings, key, "INFO")?);
+            let (value_type, count) = shared.descriptor()?;
+            if count > 0 && value_type != 0 {
+                line.push(b'=');
+                shared.write_values(value_type, count, false, line)?;
+            }
+        }
+
+        if !self.header.samples().is_empty() {
+            if n_sample != self.header.samples().len() {
+                return Err(anyhow::anyhow!(
+                    "Number of samples in BCF record does not match the header"
+                ));
+            }
+            let mut individual = Cursor {
+                data: &self.individual,
+                position: 0,
+            };
+            let mut samples = vec![Vec::new(); n_sample];
+            line.push(b'\t');
+            if n_fmt == 0 {
+                line.push(b'.');
+            }
+            for i in 0..n_fmt {
+                if i > 0 {
+                    line.push(b':');
+                }
+                let key = individual.typed_int()?;
+                let key = Self::dictionary_value(&self.strings, key, "FORMAT")?;
+                line.extend_from_slice(key);
+                let (value_type, count) = individual.descriptor()?;
+                for one in samples.iter_mut() {
+                    if i > 0 {
+                        one.push(b':');
+                    }
+                    individual.write_values(value_type, count, key == b"GT", one)?;
+                }
+            }
+            for one in samples {
+                line.push(b'\t');
+                if one.is_empty() {
+                    line.push(b'.');
+                } else {
+                    line.extend_from_slice(&one);
+                }
+            }
+        }
+        line.push(b'\n');
+        Ok(())
+    }
+}
+
+impl<R: Read> RecordSource for BcfReader<R> {
+    fn header(&self) -> &VCFHeader {
+        &self.header
+    }
+
+    fn next_record(&mut self, record: &mut VCFRecord) -> Result<bool> {
+        let mut lengths = [0; 8];
+        match self.reader.read_exact(&mut lengths) {
+            Ok(_) => (),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+        self.shared
+            .resize(u32::from_le_bytes(lengths[..4].try_into()?) as usize, 0);
+        self.reader.read_exact(&mut self.shared)?;
+        self.individual
+            .resize(u32::from_le_bytes(lengths[4..].try_into()?) as usize, 0);
+        self.reader.read_exact(&mut self.individual)?;
+        self.line_num += 1;
+        self.decode_line()
+            .with_context(|| format!("Cannot decode BCF record {}", self.line_num))?;
+        record.parse_bytes(&self.line, self.line_num)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    #[test]
+    fn test_dictionaries() {
+        let (contigs, strings) = dictionaries(
+            b"##fileformat=VCFv4.2\n\
+##contig=<ID=chr1,length=100>\n\
+##contig=<ID=chr2,length=200>\n\
+##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">\n\
+##FILTER=<ID=LowQual,Description=\"Low, IDX=0\",IDX=3>\n\
+##FORMAT=<ID=DP,Number=1,Type=Integer,Description=\"Depth\">\n\
+##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">\n",
+        );
+        assert_eq!(contigs, vec![b"chr1".to_vec(), b"chr2".to_vec()]);
+        assert_eq!(
+            strings,
+            vec![
+                b"PASS".to_vec(),
+                b"DP".to_vec(),
+                Vec::new(),
+                b"LowQual".to_vec(),
+                b"GT".to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bcf_reader() -> Result<()> {
+        let mut vcf_data_reader =
+            BufReader::new(File::open("examples/vcf/1kGP-subset-snpeff.vcf")?);
+        let mut vcf_reader = vcf::VCFReader::new(&mut vcf_data_reader)?;
+        let mut bcf_reader = BcfReader::new(MultiGzDecoder::new(File::open(
+            "examples/vcf/1kGP-subset-snpeff.bcf",
+        )?))?;
+        assert_eq!(bcf_reader.header(), vcf_reader.header());
+
+        let mut expected = vcf_reader.empty_record();
+        let mut record = vcf_reader.empty_record();
+        let mut count = 0;
+        while vcf_reader.next_record(&mut expected)? {
+            assert!(bcf_reader.next_record(&mut record)?);
+            assert_eq!(record.chromosome, expected.chromosome);
+            assert_eq!(record.position, expected.position);
+            assert_eq!(record.id, expected.id);
+            assert_eq!(record.reference, expected.reference);
+            assert_eq!(record.alternative, expected.alternative);
+            assert_eq!(record.qual, expected.qual);
+            assert_eq!(record.filter, expected.filter);
+            assert_eq!(record.info(b"ANN"), expected.info(b"ANN"));
+            assert_eq!(record.info(b"AC"), expected.info(b"AC"));
+            assert_eq!(record.format, expected.format);
+            assert_eq!(record.genotype, expected.genotype);
+            count += 1;
+        }
+        assert!(!bcf_reader.next_record(&mut record)?);
+        assert_eq!(count, 306);
+        Ok(())
+    }
+}
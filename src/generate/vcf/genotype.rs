@@ -0,0 +1,397 @@
+use crate::model::{GenotypeClass, GenotypeFilterDef, InheritanceModel};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::str;
+use vcf::{U8Vec, VCFHeader, VCFRecord};
+
+/// Classifies a GT value against one alternative allele, or any alternative allele if `alt_index` is `None`
+pub fn genotype_class(gt: &[u8], alt_index: Option<usize>) -> GenotypeClass {
+    let alleles: Vec<Option<usize>> = gt
+        .split(|x| *x == b'/' || *x == b'|')
+        .map(|x| str::from_utf8(x).ok().and_then(|y| y.parse::<usize>().ok()))
+        .collect();
+    if alleles.is_empty() || alleles.iter().any(|x| x.is_none()) {
+        return GenotypeClass::Missing;
+    }
+    let alt_count = alleles
+        .iter()
+        .flatten()
+        .filter(|x| match alt_index {
+            Some(alt_index) => **x == alt_index + 1,
+            None => **x > 0,
+        })
+        .count();
+    if alt_count == 0 {
+        GenotypeClass::HomRef
+    } else if alt_count == alleles.len() {
+        GenotypeClass::HomAlt
+    } else {
+        GenotypeClass::Het
+    }
+}
+
+fn sample_genotype_class(
+    record: &VCFRecord,
+    sample: &[u8],
+    alt_index: Option<usize>,
+) -> GenotypeClass {
+    record
+        .genotype(sample, b"GT")
+        .and_then(|x| x.first())
+        .map(|x| genotype_class(x, alt_index))
+        .unwrap_or(GenotypeClass::Missing)
+}
+
+fn sample_integer(record: &VCFRecord, sample: &[u8], key: &[u8]) -> Option<u32> {
+    record
+        .genotype(sample, key)
+        .and_then(|x| x.first())
+        .and_then(|x| str::from_utf8(x).ok())
+        .and_then(|x| x.parse::<f64>().ok())
+        .map(|x| x as u32)
+}
+
+/// Finds a sample by its ID, or by the name given in `sample-names`
+pub fn resolve_sample(
+    name: &str,
+    header: &VCFHeader,
+    replace_sample_name: Option<&[U8Vec]>,
+) -> Option<U8Vec> {
+    let samples = header.samples();
+    if let Some(found) = samples.iter().find(|x| x == &name.as_bytes()) {
+        return Some(found.clone());
+    }
+    replace_sample_name
+        .and_then(|names| names.iter().position(|x| x == name.as_bytes()))
+        .and_then(|i| samples.get(i))
+        .cloned()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenotypeFilter {
+    pub sample: U8Vec,
+    pub genotype: Option<Vec<GenotypeClass>>,
+    pub min_dp: Option<u32>,
+    pub min_gq: Option<u32>,
+}
+
+impl GenotypeFilter {
+    pub fn new(
+        def: &GenotypeFilterDef,
+        header: &VCFHeader,
+        replace_sample_name: Option<&[U8Vec]>,
+    ) -> Result<Self> {
+        let sample = resolve_sample(&def.sample, header, replace_sample_name).ok_or_else(|| {
+            anyhow::anyhow!("Sample \"{}\" in genotype filters is not found", def.sample)
+        })?;
+        Ok(GenotypeFilter {
+            sample,
+            genotype: def.genotype.clone(),
+            min_dp: def.min_dp,
+            min_gq: def.min_gq,
+        })
+    }
+
+    pub fn matches(&self, record: &VCFRecord, alt_index: Option<usize>) -> bool {
+        if let Some(genotype) = self.genotype.as_ref() {
+            if !genotype.contains(&sample_genotype_class(record, &self.sample, alt_index)) {
+                return false;
+            }
+        }
+        if let Some(min_dp) = self.min_dp {
+            if sample_integer(record, &self.sample, b"DP").map(|x| x < min_dp) != Some(false) {
+                return false;
+            }
+        }
+        if let Some(min_gq) = self.min_gq {
+            if sample_integer(record, &self.sample, b"GQ").map(|x| x < min_gq) != Some(false) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PedigreeMember {
+    pub family: String,
+    pub individual: String,
+    pub father: Option<String>,
+    pub mother: Option<String>,
+    pub affected: Option<bool>,
+}
+
+/// Loads a PED file: family, individual, father, mother, sex and phenotype columns
+pub fn load_pedigree(reader: impl BufRead) -> Result<Vec<PedigreeMember>> {
+    let mut members = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let columns: Vec<_> = line.split_whitespace().collect();
+        if columns.len() < 6 {
+            return Err(anyhow::anyhow!(
+                "Line {} of PED file has {} columns; 6 columns are required",
+                i + 1,
+                columns.len()
+            ));
+        }
+        let parent = |x: &str| (x != "0").then(|| x.to_string());
+        members.push(PedigreeMember {
+            family: columns[0].to_string(),
+            individual: columns[1].to_string(),
+            father: parent(columns[2]),
+            mother: parent(columns[3]),
+            affected: match columns[5] {
+                "2" => Some(true),
+                "1" => Some(false),
+                _ => None,
+            },
+        });
+    }
+    Ok(members)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParentOrigin {
+    Father,
+    Mother,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Trio {
+    pub child: U8Vec,
+    pub father: U8Vec,
+    pub mother: U8Vec,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct InheritanceMatch {
+    pub models: Vec<InheritanceModel>,
+    /// Trio index and the parent the variant is inherited from
+    pub compound_candidates: Vec<(usize, ParentOrigin)>,
+}
+
+impl InheritanceMatch {
+    pub fn is_empty(&self) -> bool {
+        self.models.is_empty() && self.compound_candidates.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InheritanceFilter {
+    pub models: Vec<InheritanceModel>,
+    pub trios: Vec<Trio>,
+    pub affected: Vec<U8Vec>,
+    pub unaffected: Vec<U8Vec>,
+}
+
+impl InheritanceFilter {
+    /// Members of the pedigree absent from the VCF header are ignored
+    pub fn new(
+        pedigree: &[PedigreeMember],
+        header: &VCFHeader,
+        replace_sample_name: Option<&[U8Vec]>,
+        models: &[InheritanceModel],
+    ) -> Result<Self> {
+        let resolve = |x: &str| resolve_sample(x, header, replace_sample_name);
+        let mut filter = InheritanceFilter {
+            models: if models.is_empty() {
+                InheritanceModel::ALL.to_vec()
+            } else {
+                models.to_vec()
+            },
+            trios: Vec::new(),
+            affected: Vec::new(),
+            unaffected: Vec::new(),
+        };
+
+        for member in pedigree {
+            let individual = if let Some(x) = resolve(&member.individual) {
+                x
+            } else {
+                continue;
+            };
+            match member.affected {
+                Some(true) => {
+                    if let (Some(father), Some(mother)) = (
+                        member.father.as_deref().and_then(resolve),
+                        member.mother.as_deref().and_then(resolve),
+                    ) {
+                        filter.trios.push(Trio {
+                            child: individual.clone(),
+                            father,
+                            mother,
+                        });
+                    }
+                    filter.affected.push(individual);
+                }
+                Some(false) => filter.unaffected.push(individual),
+                None => (),
+            }
+        }
+
+        if filter.affected.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No affected individual in the pedigree is found in VCF samples"
+            ));
+        }
+        if filter.trios.is_empty()
+            && filter.models.iter().any(|x| {
+                matches!(
+                    x,
+                    InheritanceModel::DeNovo | InheritanceModel::CompoundHeterozygous
+                )
+            })
+        {
+            eprintln!("WARNING: No trio is found in VCF samples; de novo and compound heterozygous variants are not detected");
+        }
+        Ok(filter)
+    }
+
+    pub fn has_compound_heterozygous(&self) -> bool {
+        self.models
+            .contains(&InheritanceModel::CompoundHeterozygous)
+    }
+
+    pub fn matches(&self, record: &VCFRecord, alt_index: Option<usize>) -> InheritanceMatch {
+        let class = |x: &U8Vec| sample_genotype_class(record, x, alt_index);
+        let mut result = InheritanceMatch::default();
+
+        for model in self.models.iter() {
+            let matched = match model {
+                InheritanceModel::DeNovo => self.trios.iter().any(|trio| {
+                    matches!(
+                        class(&trio.child),
+                        GenotypeClass::Het | GenotypeClass::HomAlt
+                    ) && class(&trio.father) == GenotypeClass::HomRef
+                        && class(&trio.mother) == GenotypeClass::HomRef
+                }),
+                InheritanceModel::HomozygousRecessive => {
+                    self.affected
+                        .iter()
+                        .all(|x| class(x) == GenotypeClass::HomAlt)
+                        && self
+                            .unaffected
+                            .iter()
+                            .all(|x| class(x) != GenotypeClass::HomAlt)
+                }
+                InheritanceModel::Dominant => {
+                    self.affected
+                        .iter()
+                        .all(|x| matches!(class(x), GenotypeClass::Het | GenotypeClass::HomAlt))
+                        && self
+                            .unaffected
+                            .iter()
+                            .all(|x| class(x) == GenotypeClass::HomRef)
+                }
+                InheritanceModel::CompoundHeterozygous => {
+                    for (i, trio) in self.trios.iter().enumerate() {
+                        if class(&trio.child) != GenotypeClass::Het {
+                            continue;
+                        }
+                        match (class(&trio.father), class(&trio.mother)) {
+                            (GenotypeClass::Het, GenotypeClass::HomRef) => {
+                                result.compound_candidates.push((i, ParentOrigin::Father))
+                            }
+                            (GenotypeClass::HomRef, GenotypeClass::Het) => {
+                                result.compound_candidates.push((i, ParentOrigin::Mother))
+                            }
+                            _ => (),
+                        }
+                    }
+                    false
+                }
+            };
+            if matched {
+                result.models.push(*model);
+            }
+        }
+
+        result
+    }
+}
+
+/// Gene of a variant and its compound heterozygous candidates
+pub type CompoundCandidate<'a> = (Option<&'a [u8]>, &'a [(usize, ParentOrigin)]);
+
+/// Marks candidates sharing a gene with a candidate inherited from the other parent of the same trio
+pub fn compound_heterozygous(candidates: &[CompoundCandidate]) -> Vec<bool> {
+    let mut origins: HashMap<(&[u8], usize), (bool, bool)> = HashMap::new();
+    for (gene, list) in candidates {
+        if let Some(gene) = gene.filter(|x| !x.is_empty()) {
+            for (trio, origin) in list.iter() {
+                let entry = origins.entry((gene, *trio)).or_default();
+                match origin {
+                    ParentOrigin::Father => entry.0 = true,
+                    ParentOrigin::Mother => entry.1 = true,
+                }
+            }
+        }
+    }
+    candidates
+        .iter()
+        .map(|(gene, list)| {
+            gene.filter(|x| !x.is_empty())
+                .map(|gene| {
+                    list.iter()
+                        .any(|(trio, _)| origins.get(&(gene, *trio)) == Some(&(true, true)))
+                })
+                .unwrap_or(false)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_genotype_class() {
+        assert_eq!(genotype_class(b"0/0", None), GenotypeClass::HomRef);
+        assert_eq!(genotype_class(b"0|1", None), GenotypeClass::Het);
+        assert_eq!(genotype_class(b"1/2", None), GenotypeClass::HomAlt);
+        assert_eq!(genotype_class(b"1/2", Some(0)), GenotypeClass::Het);
+        assert_eq!(genotype_class(b"2/2", Some(0)), GenotypeClass::HomRef);
+        assert_eq!(genotype_class(b"1", None), GenotypeClass::HomAlt);
+        assert_eq!(genotype_class(b"./1", None), GenotypeClass::Missing);
+        assert_eq!(genotype_class(b".", None), GenotypeClass::Missing);
+    }
+
+    #[test]
+    fn test_load_pedigree() -> Result<()> {
+        let data = b"# family\nFAM1 child dad mom 1 2\nFAM1 dad 0 0 1 1\nFAM1 mom 0 0 2 -9\n";
+        let members = load_pedigree(&data[..])?;
+        assert_eq!(members.len(), 3);
+        assert_eq!(
+            members[0],
+            PedigreeMember {
+                family: "FAM1".to_string(),
+                individual: "child".to_string(),
+                father: Some("dad".to_string()),
+                mother: Some("mom".to_string()),
+                affected: Some(true),
+            }
+        );
+        assert_eq!(members[1].father, None);
+        assert_eq!(members[2].affected, None);
+        assert!(load_pedigree(&b"FAM1 child dad mom\n"[..]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compound_heterozygous() {
+        let father = [(0, ParentOrigin::Father)];
+        let mother = [(0, ParentOrigin::Mother)];
+        let result = compound_heterozygous(&[
+            (Some(b"GENE1"), &father),
+            (Some(b"GENE2"), &father),
+            (Some(b"GENE1"), &mother),
+            (None, &mother),
+            (Some(b"GENE2"), &[]),
+        ]);
+        assert_eq!(result, vec![true, false, true, false, false]);
+    }
+}
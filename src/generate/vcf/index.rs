@@ -0,0 +1,376 @@
+use super::region::RegionSet;
+use super::RecordSource;
+use anyhow::{Context, Result};
+use flate2::read::MultiGzDecoder;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use vcf::{U8Vec, VCFHeader, VCFRecord};
+
+const TBI_MIN_SHIFT: u32 = 14;
+const TBI_DEPTH: u32 = 5;
+
+/// Finds a tabix or CSI index next to a BGZF compressed VCF
+pub fn find_index(path: &Path) -> Option<PathBuf> {
+    ["tbi", "csi"]
+        .iter()
+        .map(|x| {
+            let mut index_path = path.as_os_str().to_os_string();
+            index_path.push(".");
+            index_path.push(x);
+            PathBuf::from(index_path)
+        })
+        .find(|x| x.is_file())
+}
+
+#[derive(Debug, Default)]
+struct ReferenceIndex {
+    bins: HashMap<u32, Vec<(u64, u64)>>,
+    linear: Vec<u64>,
+}
+
+#[derive(Debug)]
+pub struct VcfIndex {
+    names: Vec<U8Vec>,
+    min_shift: u32,
+    depth: u32,
+    references: Vec<ReferenceIndex>,
+}
+
+struct IndexReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> IndexReader<R> {
+    fn bytes(&mut self, length: usize) -> Result<Vec<u8>> {
+        let mut buffer = vec![0; length];
+        self.reader.read_exact(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        let mut buffer = [0; 4];
+        self.reader.read_exact(&mut buffer)?;
+        Ok(i32::from_le_bytes(buffer))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let mut buffer = [0; 4];
+        self.reader.read_exact(&mut buffer)?;
+        Ok(u32::from_le_bytes(buffer))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let mut buffer = [0; 8];
+        self.reader.read_exact(&mut buffer)?;
+        Ok(u64::from_le_bytes(buffer))
+    }
+
+    fn count(&mut self) -> Result<usize> {
+        let value = self.i32()?;
+        if value < 0 {
+            return Err(anyhow::anyhow!("Negative count in index: {}", value));
+        }
+        Ok(value as usize)
+    }
+
+    fn chunks(&mut self) -> Result<Vec<(u64, u64)>> {
+        let n_chunk = self.count()?;
+        (0..n_chunk)
+            .map(|_| Ok((self.u64()?, self.u64()?)))
+            .collect()
+    }
+
+    /// Tabix header fields shared by TBI and the auxiliary data of CSI
+    fn names(&mut self) -> Result<Vec<U8Vec>> {
+        // format, col_seq, col_beg, col_end, meta, skip
+        for _ in 0..6 {
+            self.i32()?;
+        }
+        let length = self.count()?;
+        Ok(self
+            .bytes(length)?
+            .split(|x| *x == 0)
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_vec())
+            .collect())
+    }
+}
+
+fn reg2bins(start: u64, end: u64, min_shift: u32, depth: u32) -> Vec<u32> {
+    let end = end.max(start + 1) - 1;
+    let mut bins = Vec::new();
+    let mut t = 0u64;
+    let mut shift = min_shift + depth * 3;
+    for level in 0..=depth {
+        let first = t + (start >> shift);
+        let last = t + (end >> shift);
+        bins.extend((first..=last).map(|x| x as u32));
+        shift = shift.saturating_sub(3);
+        t += 1 << (level * 3);
+    }
+    bins
+}
+
+impl VcfIndex {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+        let mut reader = IndexReader {
+            reader: MultiGzDecoder::new(BufReader::new(file)),
+        };
+        let magic = reader.bytes(4)?;
+        let (names, min_shift, depth, csi) = match &magic[..] {
+            b"TBI\x01" => {
+                let n_ref = reader.count()?;
+                let names = reader.names()?;
+                if names.len() != n_ref {
+                    return Err(anyhow::anyhow!("Broken tabix index: {}", path.display()));
+                }
+                (names, TBI_MIN_SHIFT, TBI_DEPTH, false)
+            }
+            b"CSI\x01" => {
+                let min_shift = reader.u32()?;
+                let depth = reader.u32()?;
+                let l_aux = reader.count()?;
+                let names = if l_aux >= 28 {
+                    let aux = reader.bytes(l_aux)?;
+                    IndexReader { reader: &aux[..] }.names()?
+                } else {
+                    reader.bytes(l_aux)?;
+                    Vec::new()
+                };
+                (names, min_shift, depth, true)
+            }
+            _ => return Err(anyhow::anyhow!("Unknown index format: {}", path.display())),
+        };
+        let n_ref = if csi { reader.count()? } else { names.len() };
+        if names.len() != n_ref {
+            return Err(anyhow::anyhow!(
+                "Sequence names are not found in index: {}",
+                path.display()
+            ));
+        }
+
+        let mut references = Vec::new();
+        for _ in 0..n_ref {
+            let mut reference = ReferenceIndex::default();
+            let n_bin = reader.count()?;
+            for _ in 0..n_bin {
+                let bin = reader.u32()?;
+                if csi {
+                    // loffset
+                    reader.u64()?;
+                }
+                reference.bins.insert(bin, reader.chunks()?);
+            }
+            if !csi {
+                let n_intv = reader.count()?;
+                reference.linear = (0..n_intv).map(|_| reader.u64()).collect::<Result<_>>()?;
+            }
+            references.push(reference);
+        }
+
+        Ok(VcfIndex {
+            names,
+            min_shift,
+            depth,
+            references,
+        })
+    }
+
+    pub fn names(&self) -> &[U8Vec] {
+        &self.names
+    }
+
+    /// Virtual offset to start scanning records in a 1-based closed interval
+    pub fn start_offset(&self, chrom: &[u8], start: u64, end: u64) -> Option<u64> {
+        let reference = &self.references[self.names.iter().position(|x| x == chrom)?];
+        let start = start - 1;
+        let min_offset = reference
+            .linear
+            .get((start >> TBI_MIN_SHIFT) as usize)
+            .or_else(|| reference.linear.last())
+            .copied()
+            .unwrap_or(0);
+        reg2bins(
+            start,
+            end.min(1 << (self.min_shift + self.depth * 3)),
+            self.min_shift,
+            self.depth,
+        )
+        .iter()
+        .filter_map(|x| reference.bins.get(x))
+        .flatten()
+        .filter(|x| x.1 > min_offset)
+        .map(|x| x.0)
+        .min()
+    }
+}
+
+/// Reads records overlapping the regions from a BGZF compressed VCF with its index
+pub struct IndexedReader {
+    path: PathBuf,
+    header: VCFHeader,
+    index: VcfIndex,
+    regions: Vec<(U8Vec, u64, u64)>,
+    next_region: usize,
+    current: Option<Box<dyn BufRead>>,
+    line: U8Vec,
+    line_num: u64,
+}
+
+impl IndexedReader {
+    pub fn new(path: &Path, index_path: &Path, regions: &RegionSet) -> Result<Self> {
+        let header = vcf::VCFReader::new(BufReader::new(MultiGzDecoder::new(BufReader::new(
+            File::open(path).with_context(|| format!("Cannot open {}", path.display()))?,
+        ))))?
+        .header()
+        .clone();
+        let index = VcfIndex::load(index_path)?;
+        for chrom in regions.chromosomes() {
+            if !index.names().iter().any(|x| x == chrom) {
+                eprintln!(
+                    "WARNING: Chromosome \"{}\" in regions is not found in the index of {}",
+                    String::from_utf8_lossy(chrom),
+                    path.display()
+                );
+            }
+        }
+        let regions = index
+            .names()
+            .iter()
+            .flat_map(|chrom| {
+                regions
+                    .intervals(chrom)
+                    .iter()
+                    .map(move |x| (chrom.clone(), x.0, x.1))
+            })
+            .collect();
+        Ok(IndexedReader {
+            path: path.to_path_buf(),
+            header,
+            index,
+            regions,
+            next_region: 0,
+            current: None,
+            line: Vec::new(),
+            line_num: 0,
+        })
+    }
+
+    fn open_at(&self, virtual_offset: u64) -> Result<Box<dyn BufRead>> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(virtual_offset >> 16))?;
+        let mut reader = BufReader::new(MultiGzDecoder::new(BufReader::new(file)));
+        std::io::copy(
+            &mut (&mut reader).take(virtual_offset & 0xffff),
+            &mut std::io::sink(),
+        )?;
+        Ok(Box::new(reader))
+    }
+}
+
+impl RecordSource for IndexedReader {
+    fn header(&self) -> &VCFHeader {
+        &self.header
+    }
+
+    fn next_record(&mut self, record: &mut VCFRecord) -> Result<bool> {
+        loop {
+            let (chrom, start, end) = match self.current.as_ref() {
+                Some(_) => self.regions[self.next_region - 1].clone(),
+                None => {
+                    let region = match self.regions.get(self.next_region) {
+                        Some(x) => x.clone(),
+                        None => return Ok(false),
+                    };
+                    self.next_region += 1;
+                    if let Some(offset) = self.index.start_offset(&region.0, region.1, region.2) {
+                        self.current = Some(self.open_at(offset)?);
+                    }
+                    continue;
+                }
+            };
+
+            self.line.clear();
+            self.current
+                .as_mut()
+                .unwrap()
+                .read_until(b'\n', &mut self.line)?;
+            self.line_num += 1;
+            if self.line.is_empty() {
+                self.current = None;
+                continue;
+            }
+            if self.line.starts_with(b"#") {
+                continue;
+            }
+            record.parse_bytes(&self.line, self.line_num)?;
+            if record.chromosome != chrom || record.position > end {
+                self.current = None;
+                continue;
+            }
+            let record_end = record.position + (record.reference.len().max(1) as u64) - 1;
+            // Records overlapping the previous region of the same chromosome are already written
+            let written = self.next_region >= 2
+                && self.regions[self.next_region - 2].0 == chrom
+                && record.position <= self.regions[self.next_region - 2].2;
+            if record_end >= start && !written {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::region::{Region, RegionFilter};
+    use super::*;
+
+    #[test]
+    fn test_reg2bins() {
+        assert_eq!(reg2bins(0, 1, 14, 5), vec![0, 1, 9, 73, 585, 4681]);
+        assert_eq!(
+            reg2bins(16383, 16385, 14, 5),
+            vec![0, 1, 9, 73, 585, 4681, 4682]
+        );
+    }
+
+    #[test]
+    fn test_indexed_reader() -> Result<()> {
+        let path = Path::new("examples/vcf/1kGP-subset-snpeff.vcf.gz");
+        let index_path = find_index(path).unwrap();
+        let regions = RegionSet::new(
+            &[
+                "13:32900000-32910000",
+                "13:32905000-32920000",
+                "13:32972000",
+                "13:32990878-33000000",
+                "17:41196312-41277500",
+            ]
+            .iter()
+            .map(|x| Region::parse(x))
+            .collect::<Result<Vec<_>>>()?,
+        );
+
+        let mut expected = Vec::new();
+        let mut vcf_data_reader =
+            BufReader::new(File::open("examples/vcf/1kGP-subset-snpeff.vcf")?);
+        let mut reader =
+            RegionFilter::new(vcf::VCFReader::new(&mut vcf_data_reader)?, regions.clone());
+        let mut record = VCFRecord::new(reader.header().clone());
+        while reader.next_record(&mut record)? {
+            expected.push((record.position, record.reference.clone()));
+        }
+        assert!(expected.len() > 20);
+
+        let mut positions = Vec::new();
+        let mut reader = IndexedReader::new(path, &index_path, &regions)?;
+        while reader.next_record(&mut record)? {
+            positions.push((record.position, record.reference.clone()));
+        }
+        assert_eq!(positions, expected);
+        Ok(())
+    }
+}
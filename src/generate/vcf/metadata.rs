@@ -0,0 +1,192 @@
+use vcf::{VCFHeader, VCFHeaderContent};
+
+pub const METADATA_COLUMNS: [&str; 5] = ["Category", "ID", "Number", "Type", "Description"];
+
+const MAX_CONTIG_NAMES: usize = 30;
+
+fn number_to_string(number: &vcf::Number) -> String {
+    match number {
+        vcf::Number::Number(x) => x.to_string(),
+        vcf::Number::Reference => "R".to_string(),
+        vcf::Number::Allele => "A".to_string(),
+        vcf::Number::Genotype => "G".to_string(),
+        vcf::Number::Zero => "0".to_string(),
+        vcf::Number::Unknown => ".".to_string(),
+        vcf::Number::Other(x) => String::from_utf8_lossy(x).to_string(),
+    }
+}
+
+fn value_type_to_string(value_type: &vcf::ValueType) -> String {
+    match value_type {
+        vcf::ValueType::String => "String".to_string(),
+        vcf::ValueType::Integer => "Integer".to_string(),
+        vcf::ValueType::Flag => "Flag".to_string(),
+        vcf::ValueType::Character => "Character".to_string(),
+        vcf::ValueType::Float => "Float".to_string(),
+        vcf::ValueType::Other(x) => String::from_utf8_lossy(x).to_string(),
+    }
+}
+
+/// Category of a generic header line worth showing to reviewers
+fn generic_category(key: &str) -> Option<&'static str> {
+    if key == "fileformat" {
+        Some("fileformat")
+    } else if key == "reference" || key == "assembly" {
+        Some("reference")
+    } else if key == "source"
+        || key.starts_with("SnpEff")
+        || key.starts_with("VEP")
+        || key.ends_with("Command")
+        || key.ends_with("CommandLine")
+        || key.ends_with("_command")
+        || key.ends_with("Version")
+    {
+        Some("command")
+    } else {
+        None
+    }
+}
+
+/// Rows describing the reference, callers, filters, fields and samples of a VCF header
+pub fn metadata_rows(header: &VCFHeader) -> Vec<[String; 5]> {
+    let text = |x: &[u8]| String::from_utf8_lossy(x).to_string();
+    let mut generic = Vec::new();
+    let mut contigs = Vec::new();
+    let mut fields = Vec::new();
+
+    for item in header.items() {
+        match item.contents() {
+            VCFHeaderContent::FILTER { id, description } => fields.push([
+                "FILTER".to_string(),
+                text(id),
+                "".to_string(),
+                "".to_string(),
+                text(description),
+            ]),
+            VCFHeaderContent::ALT { id, description } => fields.push([
+                "ALT".to_string(),
+                text(id),
+                "".to_string(),
+                "".to_string(),
+                text(description),
+            ]),
+            VCFHeaderContent::INFO {
+                id,
+                number,
+                value_type,
+                description,
+                ..
+            } => fields.push([
+                "INFO".to_string(),
+                text(id),
+                number_to_string(number),
+                value_type_to_string(value_type),
+                text(description),
+            ]),
+            VCFHeaderContent::FORMAT {
+                id,
+                number,
+                value_type,
+                description,
+                ..
+            } => fields.push([
+                "FORMAT".to_string(),
+                text(id),
+                number_to_string(number),
+                value_type_to_string(value_type),
+                text(description),
+            ]),
+            VCFHeaderContent::Contig { id, .. } => contigs.push(text(id)),
+            VCFHeaderContent::FileFormat(_) | VCFHeaderContent::Other => {
+                let line = text(item.line());
+                let line = line.trim_end().trim_start_matches('#');
+                if let Some((key, value)) = line.split_once('=') {
+                    if let Some(category) = generic_category(key) {
+                        generic.push([
+                            category.to_string(),
+                            key.to_string(),
+                            "".to_string(),
+                            "".to_string(),
+                            value.trim_matches('"').to_string(),
+                        ]);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut rows = generic;
+    if !contigs.is_empty() {
+        let mut names = contigs
+            .iter()
+            .take(MAX_CONTIG_NAMES)
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(", ");
+        if contigs.len() > MAX_CONTIG_NAMES {
+            names.push_str(", ...");
+        }
+        rows.push([
+            "contig".to_string(),
+            "".to_string(),
+            contigs.len().to_string(),
+            "".to_string(),
+            names,
+        ]);
+    }
+    rows.extend(fields);
+    rows.extend(header.samples().iter().map(|x| {
+        [
+            "sample".to_string(),
+            text(x),
+            "".to_string(),
+            "".to_string(),
+            "".to_string(),
+        ]
+    }));
+    rows
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::BufReader;
+
+    #[test]
+    fn test_metadata_rows() -> anyhow::Result<()> {
+        let vcf_data = include_bytes!("../../../examples/vcf/simple1-snpeff.vcf");
+        let mut vcf_data_reader = BufReader::new(&vcf_data[..]);
+        let vcf_reader = vcf::VCFReader::new(&mut vcf_data_reader)?;
+        let rows = metadata_rows(vcf_reader.header());
+
+        assert_eq!(
+            rows[0],
+            [
+                "fileformat".to_string(),
+                "fileformat".to_string(),
+                "".to_string(),
+                "".to_string(),
+                "VCFv4.2".to_string()
+            ]
+        );
+        let categories: Vec<_> = rows.iter().map(|x| &x[0][..]).collect();
+        assert_eq!(categories.iter().filter(|x| **x == "command").count(), 8);
+        let snpeff_cmd = rows.iter().find(|x| x[1] == "SnpEffCmd").unwrap();
+        assert_eq!(
+            snpeff_cmd[4],
+            "SnpEff  GENCODE33.GRCh37.primary_assembly simple1.vcf "
+        );
+        let contig = rows.iter().find(|x| x[0] == "contig").unwrap();
+        assert_eq!(
+            &contig[2..],
+            &["2".to_string(), "".to_string(), "13, 14".to_string()]
+        );
+        let ad = rows
+            .iter()
+            .find(|x| x[0] == "FORMAT" && x[1] == "AD")
+            .unwrap();
+        assert_eq!(&ad[2..4], &["R".to_string(), "Integer".to_string()]);
+        assert_eq!(&categories[categories.len() - 2..], &["sample", "sample"]);
+        Ok(())
+    }
+}
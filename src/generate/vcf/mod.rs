@@ -1,6 +1,15 @@
+pub mod annotation;
+pub mod bcf;
+pub mod genotype;
+pub mod index;
+pub mod metadata;
+pub mod region;
 pub mod tablewriter;
 
-use anyhow::Result;
+use crate::model::{AnnotationTranscript, InheritanceModel, MissingFieldPolicy};
+use annotation::{Annotation, AnnotationLayout, FieldPart};
+use anyhow::{Context, Result};
+use genotype::{GenotypeFilter, InheritanceFilter, InheritanceMatch};
 use nom::branch::alt;
 use nom::bytes::complete::{tag, take_while1};
 use nom::character::is_digit;
@@ -25,6 +34,29 @@ pub struct VCF2CSVConfig {
     pub format_list: Vec<U8Vec>,
     pub replace_sample_name: Option<Vec<U8Vec>>,
     pub group_names: Option<Vec<U8Vec>>,
+    pub annotation_fields: Vec<String>,
+    pub annotation_transcript: AnnotationTranscript,
+    pub split_annotations: bool,
+    pub samples: Option<HashSet<U8Vec>>,
+    pub missing_field: MissingFieldPolicy,
+    pub genotype_filters: Vec<GenotypeFilter>,
+    pub inheritance: Option<InheritanceFilter>,
+}
+
+/// Source of VCF records, such as a whole file or records in regions
+pub trait RecordSource {
+    fn header(&self) -> &VCFHeader;
+    fn next_record(&mut self, record: &mut VCFRecord) -> Result<bool>;
+}
+
+impl<R: BufRead> RecordSource for VCFReader<R> {
+    fn header(&self) -> &VCFHeader {
+        VCFReader::header(self)
+    }
+
+    fn next_record(&mut self, record: &mut VCFRecord) -> Result<bool> {
+        Ok(VCFReader::next_record(self, record)?)
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
@@ -67,6 +99,7 @@ pub enum HeaderType {
     GroupName,
     VcfLine,
     AltIndex,
+    AnnotationIndex,
     CanonicalChange,
     GeneName,
     TranscriptName,
@@ -79,9 +112,12 @@ pub enum HeaderType {
     ALT,
     QUAL,
     FILTER,
+    Inheritance,
     SnpEff,
     SnpEffHighestImpact,
     SnpEffImpact(SnpEffImpact),
+    // AnnotationField: Name, Field index in the annotation layout, Part of the field
+    AnnotationField(String, usize, FieldPart),
     Info(U8Vec, vcf::Number, vcf::ValueType, i32, String),
     // Genotype: SampleName, FormatID, Number, Type, Index, Description, ReplacedSampleName
     Genotype(
@@ -130,6 +166,7 @@ impl ToString for HeaderType {
             HeaderType::GroupName => "Group Name".to_string(),
             HeaderType::VcfLine => "#".to_string(),
             HeaderType::AltIndex => "alt #".to_string(),
+            HeaderType::AnnotationIndex => "annotation #".to_string(),
             HeaderType::CanonicalChange => "Canonical Gene".to_string(),
             HeaderType::GeneName => "Gene".to_string(),
             HeaderType::TranscriptName => "Transcript".to_string(),
@@ -142,8 +179,9 @@ impl ToString for HeaderType {
             HeaderType::ALT => "ALT".to_string(),
             HeaderType::QUAL => "QUAL".to_string(),
             HeaderType::FILTER => "FILTER".to_string(),
+            HeaderType::Inheritance => "Inheritance".to_string(),
             HeaderType::Info(x, num, _, index, _) => {
-                let mut s = str::from_utf8(x).unwrap().to_string();
+                let mut s = String::from_utf8_lossy(x).to_string();
                 add_number_suffix(&mut s, num, *index).unwrap();
                 s
             }
@@ -151,14 +189,14 @@ impl ToString for HeaderType {
                 let mut s = if let Some(replace_sample_name) = replace_sample_name {
                     format!(
                         "{}__{}",
-                        str::from_utf8(replace_sample_name).unwrap(),
-                        str::from_utf8(y).unwrap()
+                        String::from_utf8_lossy(replace_sample_name),
+                        String::from_utf8_lossy(y)
                     )
                 } else {
                     format!(
                         "{}__{}",
-                        str::from_utf8(x).unwrap(),
-                        str::from_utf8(y).unwrap()
+                        String::from_utf8_lossy(x),
+                        String::from_utf8_lossy(y)
                     )
                 };
                 add_number_suffix(&mut s, num, *index).unwrap();
@@ -167,13 +205,38 @@ impl ToString for HeaderType {
             HeaderType::SnpEffHighestImpact => "SnpEff Impact".to_string(),
             HeaderType::SnpEffImpact(impact) => format!("GeneImpact__{}", impact.to_str()),
             HeaderType::SnpEff => "SnpEff".to_string(),
+            HeaderType::AnnotationField(name, _, _) => name.to_string(),
             HeaderType::Empty => "".to_string(),
         }
     }
 }
 
-pub fn create_header_line(header: &VCFHeader, config: &VCF2CSVConfig) -> Vec<HeaderType> {
+const MISSING_FIELD_DESCRIPTION: &str = "Not found in VCF header";
+
+fn check_missing_fields<'a>(
+    kind: &str,
+    ids: impl Iterator<Item = &'a U8Vec>,
+    found: impl Fn(&[u8]) -> bool,
+    policy: MissingFieldPolicy,
+) -> Result<()> {
+    for one in ids.filter(|x| !found(x)) {
+        let message = format!(
+            "{} field \"{}\" is not found in VCF header",
+            kind,
+            String::from_utf8_lossy(one)
+        );
+        match policy {
+            MissingFieldPolicy::Error => return Err(anyhow::anyhow!(message)),
+            MissingFieldPolicy::Skip => eprintln!("WARNING: {}; the column is skipped", message),
+            MissingFieldPolicy::Empty => (),
+        }
+    }
+    Ok(())
+}
+
+pub fn create_header_line(header: &VCFHeader, config: &VCF2CSVConfig) -> Result<Vec<HeaderType>> {
     let mut header_items = vec![HeaderType::VcfLine];
+    let annotation = AnnotationLayout::from_header(header);
 
     if config.group_names.is_some() {
         header_items.push(HeaderType::GroupName);
@@ -183,6 +246,10 @@ pub fn create_header_line(header: &VCFHeader, config: &VCF2CSVConfig) -> Vec<Hea
         header_items.push(HeaderType::AltIndex);
     }
 
+    if config.split_annotations && annotation.is_some() {
+        header_items.push(HeaderType::AnnotationIndex);
+    }
+
     header_items.append(&mut vec![
         HeaderType::CHROM,
         HeaderType::POS,
@@ -191,141 +258,176 @@ pub fn create_header_line(header: &VCFHeader, config: &VCF2CSVConfig) -> Vec<Hea
         HeaderType::ALT,
     ]);
 
-    if header.info(b"ANN").is_some() {
-        if config.canonical_list.is_some() {
+    if config.inheritance.is_some() {
+        header_items.push(HeaderType::Inheritance);
+    }
+
+    if let Some(annotation) = annotation.as_ref() {
+        if config.canonical_list.is_some() || annotation.has_canonical_flags() {
             header_items.append(&mut vec![HeaderType::CanonicalChange]);
-        };
+        } else if config.annotation_transcript == AnnotationTranscript::Canonical {
+            return Err(anyhow::anyhow!(
+                "annotation-transcript \"canonical\" requires a canonical transcript list or CANONICAL/MANE_SELECT fields in {} annotation",
+                String::from_utf8_lossy(&annotation.key)
+            ));
+        }
         header_items.append(&mut vec![HeaderType::SnpEffHighestImpact]);
+
+        let annotation_fields = if config.split_annotations && config.annotation_fields.is_empty() {
+            &annotation.fields
+        } else {
+            &config.annotation_fields
+        };
+        for name in annotation_fields.iter() {
+            if let Some((index, part)) = annotation.find_field(name) {
+                header_items.push(HeaderType::AnnotationField(name.to_string(), index, part));
+            } else {
+                eprintln!(
+                    "WARNING: {} is not found in {} annotation fields",
+                    name,
+                    String::from_utf8_lossy(&annotation.key)
+                );
+            }
+        }
     }
 
-    let add_info = |one_info: &[u8], header_items: &mut Vec<HeaderType>| {
-        let info = header.info(one_info).expect(&format!(
-            "{} is not found in format list",
-            String::from_utf8_lossy(one_info)
-        ));
-        match info.number {
-            vcf::Number::Number(x) => {
-                for i in 0..*x {
+    check_missing_fields(
+        "INFO",
+        config
+            .priority_info_list
+            .iter()
+            .chain(config.info_list.iter()),
+        |x| header.info(x).is_some(),
+        config.missing_field,
+    )?;
+    check_missing_fields(
+        "FORMAT",
+        config
+            .priority_format_list
+            .iter()
+            .chain(config.format_list.iter()),
+        |x| header.format(x).is_some(),
+        config.missing_field,
+    )?;
+
+    let add_info = |one_info: &[u8], header_items: &mut Vec<HeaderType>| -> Result<()> {
+        let info = match header.info(one_info) {
+            Some(info) => info,
+            None => {
+                if config.missing_field == MissingFieldPolicy::Empty {
                     header_items.push(HeaderType::Info(
                         one_info.to_vec(),
-                        info.number.clone(),
-                        info.value_type.clone(),
-                        i,
-                        str::from_utf8(info.description).unwrap().to_string(),
+                        vcf::Number::Number(1),
+                        vcf::ValueType::String,
+                        0,
+                        MISSING_FIELD_DESCRIPTION.to_string(),
                     ));
                 }
+                return Ok(());
             }
-            vcf::Number::Reference => {
-                header_items.push(HeaderType::Info(
-                    one_info.to_vec(),
-                    info.number.clone(),
-                    info.value_type.clone(),
-                    0,
-                    str::from_utf8(info.description).unwrap().to_string(),
-                ));
-                header_items.push(HeaderType::Info(
-                    one_info.to_vec(),
-                    info.number.clone(),
-                    info.value_type.clone(),
-                    1,
-                    str::from_utf8(info.description).unwrap().to_string(),
-                ));
-            }
-            _ => {
-                header_items.push(HeaderType::Info(
-                    one_info.to_vec(),
-                    info.number.clone(),
-                    info.value_type.clone(),
-                    0,
-                    str::from_utf8(info.description).unwrap().to_string(),
-                ));
-            }
+        };
+        let description = str::from_utf8(info.description)
+            .with_context(|| {
+                format!(
+                    "Invalid description of INFO field \"{}\"",
+                    String::from_utf8_lossy(one_info)
+                )
+            })?
+            .to_string();
+        let indexes = match info.number {
+            vcf::Number::Number(x) => 0..*x,
+            vcf::Number::Reference => 0..2,
+            _ => 0..1,
+        };
+        for i in indexes {
+            header_items.push(HeaderType::Info(
+                one_info.to_vec(),
+                info.number.clone(),
+                info.value_type.clone(),
+                i,
+                description.clone(),
+            ));
         }
+        Ok(())
     };
 
     let add_format = |sample_index: usize,
                       one_sample: &[u8],
                       one_format: &[u8],
-                      header_items: &mut Vec<HeaderType>| {
-        let format = header.format(one_format).expect(&format!(
-            "{} is not found in format list",
-            String::from_utf8_lossy(one_format)
-        ));
-        match format.number {
-            vcf::Number::Number(x) => {
-                for i in 0..*x {
+                      header_items: &mut Vec<HeaderType>|
+     -> Result<()> {
+        let replace_sample_name = config
+            .replace_sample_name
+            .as_ref()
+            .and_then(|x| x.get(sample_index).map(|y| y.to_vec()));
+        let format = match header.format(one_format) {
+            Some(format) => format,
+            None => {
+                if config.missing_field == MissingFieldPolicy::Empty {
                     header_items.push(HeaderType::Genotype(
                         one_sample.to_vec(),
                         one_format.to_vec(),
-                        format.number.clone(),
-                        format.value_type.clone(),
-                        i,
-                        str::from_utf8(format.description).unwrap().to_string(),
-                        config
-                            .replace_sample_name
-                            .as_ref()
-                            .map(|x| x.get(sample_index).map(|y| y.to_vec()))
-                            .flatten(),
+                        vcf::Number::Number(1),
+                        vcf::ValueType::String,
+                        0,
+                        MISSING_FIELD_DESCRIPTION.to_string(),
+                        replace_sample_name,
                     ));
                 }
+                return Ok(());
             }
-            vcf::Number::Reference => {
-                header_items.push(HeaderType::Genotype(
-                    one_sample.to_vec(),
-                    one_format.to_vec(),
-                    format.number.clone(),
-                    format.value_type.clone(),
-                    0,
-                    str::from_utf8(format.description).unwrap().to_string(),
-                    config
-                        .replace_sample_name
-                        .as_ref()
-                        .map(|x| x.get(sample_index).map(|y| y.to_vec()))
-                        .flatten(),
-                ));
-                header_items.push(HeaderType::Genotype(
-                    one_sample.to_vec(),
-                    one_format.to_vec(),
-                    format.number.clone(),
-                    format.value_type.clone(),
-                    1,
-                    str::from_utf8(format.description).unwrap().to_string(),
-                    config
-                        .replace_sample_name
-                        .as_ref()
-                        .map(|x| x.get(sample_index).map(|y| y.to_vec()))
-                        .flatten(),
-                ));
-            }
-            _ => {
-                header_items.push(HeaderType::Genotype(
-                    one_sample.to_vec(),
-                    one_format.to_vec(),
-                    format.number.clone(),
-                    format.value_type.clone(),
-                    0,
-                    str::from_utf8(format.description).unwrap().to_string(),
-                    config
-                        .replace_sample_name
-                        .as_ref()
-                        .map(|x| x.get(sample_index).map(|y| y.to_vec()))
-                        .flatten(),
-                ));
-            }
+        };
+        let description = str::from_utf8(format.description)
+            .with_context(|| {
+                format!(
+                    "Invalid description of FORMAT field \"{}\"",
+                    String::from_utf8_lossy(one_format)
+                )
+            })?
+            .to_string();
+        let indexes = match format.number {
+            vcf::Number::Number(x) => 0..*x,
+            vcf::Number::Reference => 0..2,
+            _ => 0..1,
+        };
+        for i in indexes {
+            header_items.push(HeaderType::Genotype(
+                one_sample.to_vec(),
+                one_format.to_vec(),
+                format.number.clone(),
+                format.value_type.clone(),
+                i,
+                description.clone(),
+                replace_sample_name.clone(),
+            ));
         }
+        Ok(())
     };
 
+    let selected_samples: Vec<_> = header
+        .samples()
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| {
+            config
+                .samples
+                .as_ref()
+                .map(|y| y.contains(*x))
+                .unwrap_or(true)
+        })
+        .collect();
+
     for one_info in &config.priority_info_list {
-        add_info(one_info, &mut header_items);
+        add_info(one_info, &mut header_items)?;
     }
 
-    for (sample_index, one_sample) in header.samples().iter().enumerate() {
+    for (sample_index, one_sample) in selected_samples.iter().copied() {
         for one_format in &config.priority_format_list {
-            add_format(sample_index, &one_sample, &one_format, &mut header_items);
+            add_format(sample_index, one_sample, one_format, &mut header_items)?;
         }
     }
 
-    if header.info(b"ANN").is_some() {
+    if annotation.is_some() {
         header_items.append(&mut vec![
             HeaderType::SnpEff,
             HeaderType::SnpEffImpact(SnpEffImpact::High),
@@ -346,16 +448,16 @@ pub fn create_header_line(header: &VCFHeader, config: &VCF2CSVConfig) -> Vec<Hea
     header_items.append(&mut vec![HeaderType::QUAL, HeaderType::FILTER]);
 
     for one_info in &config.info_list {
-        add_info(one_info, &mut header_items);
+        add_info(one_info, &mut header_items)?;
     }
 
-    for (sample_index, one_sample) in header.samples().iter().enumerate() {
+    for (sample_index, one_sample) in selected_samples.iter().copied() {
         for one_format in &config.format_list {
-            add_format(sample_index, &one_sample, &one_format, &mut header_items);
+            add_format(sample_index, one_sample, one_format, &mut header_items)?;
         }
     }
 
-    header_items
+    Ok(header_items)
 }
 
 fn write_comma_separated_values(writer: &mut U8Vec, values: &[U8Vec]) {
@@ -414,174 +516,137 @@ fn write_value_for_alt_index(
     }
 }
 
-fn write_value_for_snpeff_ann(
+fn write_value_for_annotation(
+    layout: &AnnotationLayout,
     record: &VCFRecord,
     writer: &mut U8Vec,
     alt_index: usize,
 ) -> Result<()> {
-    let mut first_record = true;
-    if let Some(snpeff_record) = record.info(b"ANN") {
-        for one in snpeff_record {
-            let mut ann = one.splitn(2, |x| *x == b'|');
-            if ann.next() == Some(&record.alternative[alt_index]) {
-                if first_record {
-                    first_record = false;
-                } else {
-                    write!(writer, ",")?;
-                }
-                writer.write_all(one)?;
-            }
+    for (i, one) in layout
+        .annotations(record, Some(alt_index))
+        .iter()
+        .enumerate()
+    {
+        if i != 0 {
+            write!(writer, ",")?;
         }
+        writer.write_all(one.raw)?;
     }
     Ok(())
 }
 
 fn write_snpeff_all(
+    layout: &AnnotationLayout,
     record: &VCFRecord,
     writer: &mut U8Vec,
     alt_index: Option<usize>,
 ) -> Result<()> {
-    if let Some(snpeff_record) = record.info(b"ANN") {
-        let alt_genotype: Option<&[u8]> = alt_index.map(|x| &record.alternative[x][..]);
-        let mut first = true;
-        for one in snpeff_record {
-            let ann: Vec<_> = one.split(|x| *x == b'|').collect();
-            let genotype = ann[0];
-            if alt_genotype.is_none() || alt_genotype == Some(genotype) {
-                if first {
-                    first = false;
-                } else {
-                    writer.extend_from_slice(b", ");
-                }
-                writer.extend_from_slice(ann[6]);
-                writer.extend_from_slice(b"(");
-                writer.extend_from_slice(ann[3]);
-                writer.extend_from_slice(b"):");
-                if ann[10].is_empty() {
-                    writer.extend_from_slice(ann[9]);
-                } else {
-                    writer.extend_from_slice(ann[10]);
-                }
-            }
+    for (i, ann) in layout.annotations(record, alt_index).iter().enumerate() {
+        if i != 0 {
+            writer.extend_from_slice(b", ");
         }
+        writer.extend_from_slice(layout.transcript(ann));
+        writer.extend_from_slice(b"(");
+        writer.extend_from_slice(layout.gene(ann));
+        writer.extend_from_slice(b"):");
+        writer.extend_from_slice(layout.change(ann));
     }
 
     Ok(())
 }
 
 fn write_impact_gene(
+    layout: &AnnotationLayout,
     record: &VCFRecord,
     writer: &mut U8Vec,
     alt_index: Option<usize>,
     header: &HeaderType,
 ) -> Result<()> {
-    if let Some(snpeff_record) = record.info(b"ANN") {
-        let alt_genotype: Option<&[u8]> = alt_index.map(|x| &record.alternative[x][..]);
-        let mut genes: HashSet<U8Vec> = HashSet::new();
-        for one in snpeff_record {
-            let ann: Vec<_> = one.split(|x| *x == b'|').collect();
-            let genotype = ann[0];
-            if alt_genotype.is_none() || alt_genotype == Some(genotype) {
-                match header {
-                    HeaderType::SnpEffImpact(impact) => {
-                        if ann[2] == impact.to_str().as_bytes() {
-                            genes.insert(ann[3].to_vec());
-                        }
-                    }
-                    _ => unreachable!(),
+    let mut genes: HashSet<U8Vec> = HashSet::new();
+    for ann in layout.annotations(record, alt_index) {
+        match header {
+            HeaderType::SnpEffImpact(impact) => {
+                if layout.impact(&ann).ok().as_ref() == Some(impact) {
+                    genes.insert(layout.gene(&ann).to_vec());
                 }
             }
+            _ => unreachable!(),
         }
-        let mut gene_list: Vec<_> = genes.iter().collect();
-        gene_list.sort();
-        for (i, one) in gene_list.iter().enumerate() {
-            if i != 0 {
-                writer.extend_from_slice(b", ");
-            }
-            writer.extend_from_slice(one);
+    }
+    let mut gene_list: Vec<_> = genes.iter().collect();
+    gene_list.sort();
+    for (i, one) in gene_list.iter().enumerate() {
+        if i != 0 {
+            writer.extend_from_slice(b", ");
         }
+        writer.extend_from_slice(one);
     }
     Ok(())
 }
 
 fn write_canonical_snpeff(
+    layout: &AnnotationLayout,
     record: &VCFRecord,
     writer: &mut U8Vec,
     alt_index: Option<usize>,
     canonical_list: Option<&HashSet<U8Vec>>,
 ) -> Result<()> {
-    if let Some(canonical_list) = canonical_list {
-        if let Some(snpeff_record) = record.info(b"ANN") {
-            let alt_genotype: Option<&[u8]> = alt_index.map(|x| &record.alternative[x][..]);
-            let mut first = true;
+    let mut first = true;
 
-            let snpeff_parsed = snpeff_record
-                .iter()
-                .map(|x| x.split(|y| *y == b'|').collect::<Vec<_>>())
-                .filter(|x| alt_genotype.is_none() || alt_genotype == Some(x[0]))
-                .filter(|x| canonical_list.contains(x[6]))
-                .collect::<Vec<_>>();
+    let snpeff_parsed = layout
+        .annotations(record, alt_index)
+        .into_iter()
+        .filter(|x| layout.is_canonical(x, canonical_list) == Some(true))
+        .collect::<Vec<_>>();
 
-            let mut highest_impact = SnpEffImpact::Modifier;
-            for one in snpeff_parsed.iter() {
-                highest_impact = highest_impact.min(one[2].try_into()?);
-            }
+    let mut highest_impact = SnpEffImpact::Modifier;
+    for one in snpeff_parsed.iter() {
+        highest_impact = highest_impact.min(layout.impact(one)?);
+    }
 
-            let have_protein_changed = snpeff_parsed.iter().any(|x| !x[10].is_empty());
+    let have_protein_changed = snpeff_parsed.iter().any(|x| !layout.hgvs_p(x).is_empty());
 
-            for ann in snpeff_parsed {
-                if !have_protein_changed || !ann[10].is_empty() {
-                    let transcript = ann[6];
-                    let gene = ann[3];
-                    let impact: SnpEffImpact = ann[2].try_into()?;
-                    if (impact < SnpEffImpact::Moderate && impact < highest_impact)
-                        || impact == SnpEffImpact::Modifier
-                    {
-                        continue;
-                    }
+    for ann in snpeff_parsed {
+        if !have_protein_changed || !layout.hgvs_p(&ann).is_empty() {
+            let impact = layout.impact(&ann)?;
+            if (impact < SnpEffImpact::Moderate && impact < highest_impact)
+                || impact == SnpEffImpact::Modifier
+            {
+                continue;
+            }
 
-                    if first {
-                        first = false;
-                    } else {
-                        write!(writer, ", ")?;
-                    }
-                    write!(
-                        writer,
-                        "{}({}):",
-                        str::from_utf8(transcript)?,
-                        str::from_utf8(gene)?
-                    )?;
-                    if ann[10].is_empty() {
-                        write!(writer, "{}", str::from_utf8(ann[9])?)?;
-                    } else {
-                        write!(writer, "{}", str::from_utf8(ann[10])?)?
-                    }
-                }
+            if first {
+                first = false;
+            } else {
+                write!(writer, ", ")?;
             }
+            write!(
+                writer,
+                "{}({}):{}",
+                str::from_utf8(layout.transcript(&ann))?,
+                str::from_utf8(layout.gene(&ann))?,
+                str::from_utf8(layout.change(&ann))?
+            )?;
         }
     }
     Ok(())
 }
 
 fn write_snpeff_impact(
+    layout: &AnnotationLayout,
     record: &VCFRecord,
     writer: &mut U8Vec,
     alt_index: Option<usize>,
     canonical_list: Option<&HashSet<U8Vec>>,
 ) -> Result<()> {
-    if let Some(snpeff_record) = record.info(b"ANN") {
-        let alt_genotype: Option<&[u8]> = alt_index.map(|x| &record.alternative[x][..]);
-
-        let snpeff_parsed = snpeff_record
-            .iter()
-            .map(|x| x.split(|y| *y == b'|').collect::<Vec<_>>())
-            .filter(|x| alt_genotype.is_none() || alt_genotype == Some(x[0]))
-            .filter(|x| canonical_list.map(|y| y.contains(x[6])).unwrap_or(true))
-            .collect::<Vec<_>>();
-
+    if record.info(&layout.key).is_some() {
         let mut impact = SnpEffImpact::Modifier;
-        for one in snpeff_parsed.iter() {
-            impact = impact.min(one[2].try_into()?);
+        for one in layout
+            .annotations(record, alt_index)
+            .iter()
+            .filter(|x| layout.is_canonical(x, canonical_list).unwrap_or(true))
+        {
+            impact = impact.min(layout.impact(one)?);
         }
         write!(writer, "{}", impact.to_str())?;
     }
@@ -589,45 +654,40 @@ fn write_snpeff_impact(
 }
 
 fn write_canonical_gene(
+    layout: &AnnotationLayout,
     record: &VCFRecord,
     writer: &mut U8Vec,
     alt_index: Option<usize>,
     header: &HeaderType,
     canonical_list: Option<&HashSet<U8Vec>>,
 ) -> Result<()> {
-    if let Some(snpeff_record) = record.info(b"ANN") {
-        let alt_genotype: Option<&[u8]> = alt_index.map(|x| &record.alternative[x][..]);
-        let mut first = true;
-        for one in snpeff_record {
-            let ann: Vec<_> = one.split(|x| *x == b'|').collect();
-            let genotype = ann[0];
-            let transcript = ann[6];
-            if (alt_genotype.is_none() || alt_genotype == Some(genotype))
-                && canonical_list
-                    .map(|x| x.contains(transcript))
-                    .unwrap_or(true)
-            {
-                if first {
-                    first = false;
-                } else {
-                    write!(writer, ", ")?;
-                }
-                match header {
-                    HeaderType::CanonicalChange => {
-                        write!(writer, "{} ", str::from_utf8(ann[3])?)?;
-                        if ann[10].is_empty() {
-                            write!(writer, "{}", str::from_utf8(ann[9])?)?;
-                        } else {
-                            write!(writer, "{}", str::from_utf8(ann[10])?)?
-                        }
-                    }
-                    HeaderType::GeneName => write!(writer, "{}", str::from_utf8(ann[3])?)?,
-                    HeaderType::TranscriptName => write!(writer, "{}", str::from_utf8(ann[6])?)?,
-                    HeaderType::AminoChange => write!(writer, "{}", str::from_utf8(ann[10])?)?,
-                    HeaderType::CDSChange => write!(writer, "{}", str::from_utf8(ann[9])?)?,
-                    _ => unreachable!(),
-                }
+    let mut first = true;
+    for ann in layout
+        .annotations(record, alt_index)
+        .iter()
+        .filter(|x| layout.is_canonical(x, canonical_list).unwrap_or(true))
+    {
+        if first {
+            first = false;
+        } else {
+            write!(writer, ", ")?;
+        }
+        match header {
+            HeaderType::CanonicalChange => {
+                write!(
+                    writer,
+                    "{} {}",
+                    str::from_utf8(layout.gene(ann))?,
+                    str::from_utf8(layout.change(ann))?
+                )?;
+            }
+            HeaderType::GeneName => write!(writer, "{}", str::from_utf8(layout.gene(ann))?)?,
+            HeaderType::TranscriptName => {
+                write!(writer, "{}", str::from_utf8(layout.transcript(ann))?)?
             }
+            HeaderType::AminoChange => write!(writer, "{}", str::from_utf8(layout.hgvs_p(ann))?)?,
+            HeaderType::CDSChange => write!(writer, "{}", str::from_utf8(layout.hgvs_c(ann))?)?,
+            _ => unreachable!(),
         }
     }
     Ok(())
@@ -690,8 +750,18 @@ pub fn vcf2table_set_data_type(
     let types: Vec<_> = header_contents
         .iter()
         .map(|x| match x {
-            HeaderType::POS | HeaderType::VcfLine | HeaderType::AltIndex => XlsxDataType::Integer,
+            HeaderType::POS
+            | HeaderType::VcfLine
+            | HeaderType::AltIndex
+            | HeaderType::AnnotationIndex => XlsxDataType::Integer,
             HeaderType::QUAL => XlsxDataType::Number,
+            HeaderType::AnnotationField(name, _, part) => {
+                if *part != FieldPart::Whole || name.eq_ignore_ascii_case("distance") {
+                    XlsxDataType::Integer
+                } else {
+                    XlsxDataType::String
+                }
+            }
             HeaderType::Info(_, _, t, _, _) => match t {
                 vcf::ValueType::Float | vcf::ValueType::Integer => XlsxDataType::Number,
                 _ => XlsxDataType::String,
@@ -718,6 +788,12 @@ pub fn vcf2table_set_data_type(
             HeaderType::CanonicalChange => {
                 "Gene annotation for canonical transcript".to_string()
             }
+            HeaderType::Inheritance => {
+                "Inheritance models matched with the pedigree".to_string()
+            }
+            HeaderType::AnnotationField(name, _, _) => {
+                format!("{} of the selected transcript annotation", name)
+            }
             HeaderType::SnpEffHighestImpact => {
                 "Highest snpeff impact (See \"Effect prediction details\" at http://snpeff.sourceforge.net/SnpEff_manual.html)".to_string()
             }
@@ -729,6 +805,21 @@ pub fn vcf2table_set_data_type(
     Ok(())
 }
 
+pub fn sample_columns(header_contents: &[HeaderType]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut last_sample: Option<&U8Vec> = None;
+    for (i, one) in header_contents.iter().enumerate() {
+        if let HeaderType::Genotype(sample, ..) = one {
+            match ranges.last_mut() {
+                Some(range) if last_sample == Some(sample) && range.1 + 1 == i => range.1 = i,
+                _ => ranges.push((i, i)),
+            }
+            last_sample = Some(sample);
+        }
+    }
+    ranges
+}
+
 pub fn column_widths(header_contents: &[HeaderType]) -> Vec<f64> {
     header_contents
         .iter()
@@ -742,7 +833,9 @@ pub fn column_widths(header_contents: &[HeaderType]) -> Vec<f64> {
         .collect()
 }
 
+#[allow(clippy::too_many_arguments)]
 fn setup_row(
+    annotation: Option<&AnnotationLayout>,
     group_name: Option<&U8Vec>,
     header_contents: &[HeaderType],
     record: &VCFRecord,
@@ -751,6 +844,8 @@ fn setup_row(
     alt_index: Option<usize>,
     translate_genotype: bool,
     canonical_list: Option<&HashSet<U8Vec>>,
+    selected: Option<&Annotation>,
+    annotation_index: Option<usize>,
 ) -> Result<()> {
     for (header, column) in header_contents.iter().zip(row.iter_mut()) {
         column.clear();
@@ -769,20 +864,30 @@ fn setup_row(
                     write!(column, "{}", alt_index + 1)?;
                 }
             }
-            HeaderType::CanonicalChange => {
-                write_canonical_snpeff(record, column, alt_index, canonical_list)?
-            }
-            HeaderType::GeneName => {
-                write_canonical_gene(record, column, alt_index, header, canonical_list)?
-            }
-            HeaderType::TranscriptName => {
-                write_canonical_gene(record, column, alt_index, header, canonical_list)?
+            HeaderType::AnnotationIndex => {
+                if let Some(annotation_index) = annotation_index {
+                    write!(column, "{}", annotation_index + 1)?;
+                }
             }
-            HeaderType::AminoChange => {
-                write_canonical_gene(record, column, alt_index, header, canonical_list)?
+            HeaderType::CanonicalChange => {
+                if let Some(annotation) = annotation {
+                    write_canonical_snpeff(annotation, record, column, alt_index, canonical_list)?
+                }
             }
-            HeaderType::CDSChange => {
-                write_canonical_gene(record, column, alt_index, header, canonical_list)?
+            HeaderType::GeneName
+            | HeaderType::TranscriptName
+            | HeaderType::AminoChange
+            | HeaderType::CDSChange => {
+                if let Some(annotation) = annotation {
+                    write_canonical_gene(
+                        annotation,
+                        record,
+                        column,
+                        alt_index,
+                        header,
+                        canonical_list,
+                    )?
+                }
             }
             HeaderType::CHROM => column.extend_from_slice(&record.chromosome),
             HeaderType::POS => {
@@ -815,12 +920,11 @@ fn setup_row(
                         write!(column, "FALSE")?;
                     }
                 } else if let Some(values) = record.info(key) {
-                    if key == b"ANN" && number == &vcf::Number::Unknown {
-                        if let Some(alt_index) = alt_index {
-                            write_value_for_snpeff_ann(record, column, alt_index)?;
-                        } else {
-                            write_value_for_alt_index(column, values, number, *index, alt_index);
-                        }
+                    let annotation = annotation.filter(|x| &x.key == key);
+                    if let (Some(annotation), vcf::Number::Unknown, Some(alt_index)) =
+                        (annotation, number, alt_index)
+                    {
+                        write_value_for_annotation(annotation, record, column, alt_index)?;
                     } else {
                         write_value_for_alt_index(column, values, number, *index, alt_index);
                     }
@@ -836,87 +940,243 @@ fn setup_row(
                 }
             }
             HeaderType::SnpEffHighestImpact => {
-                write_snpeff_impact(record, column, alt_index, canonical_list)?;
+                if let Some(annotation) = annotation {
+                    write_snpeff_impact(annotation, record, column, alt_index, canonical_list)?;
+                }
             }
             HeaderType::SnpEffImpact(_) => {
-                write_impact_gene(record, column, alt_index, header)?;
+                if let Some(annotation) = annotation {
+                    write_impact_gene(annotation, record, column, alt_index, header)?;
+                }
             }
             HeaderType::SnpEff => {
-                write_snpeff_all(record, column, alt_index)?;
+                if let Some(annotation) = annotation {
+                    write_snpeff_all(annotation, record, column, alt_index)?;
+                }
+            }
+            HeaderType::AnnotationField(_, index, part) => {
+                if let (Some(annotation), Some(selected)) = (annotation, selected) {
+                    column.extend_from_slice(annotation.field_value(selected, *index, *part));
+                }
             }
-            HeaderType::Empty => {}
+            // Filled after inheritance models are resolved
+            HeaderType::Inheritance | HeaderType::Empty => {}
         }
     }
 
     Ok(())
 }
 
-pub fn vcf2table<R: BufRead, W: TableWriter>(
-    vcf_reader: &mut VCFReader<R>,
+// Row, gene and inheritance match kept until compound heterozygous variants are resolved
+type DeferredRow = (Vec<U8Vec>, Option<U8Vec>, InheritanceMatch);
+
+pub fn vcf2table<S: RecordSource + ?Sized, W: TableWriter>(
+    vcf_reader: &mut S,
     header_contents: &[HeaderType],
     config: &VCF2CSVConfig,
     group_name: Option<&U8Vec>,
     write_header: bool,
     mut writer: W,
 ) -> Result<u32> {
-    let header_string: Vec<_> = header_contents.iter().map(|x| x.to_string()).collect();
-    writer.set_header(&header_string);
+    // Later parts of a merged table keep the header of the first part
     if write_header {
+        let header_string: Vec<_> = header_contents.iter().map(|x| x.to_string()).collect();
+        writer.set_header(&header_string);
         writer.write_header()?;
     }
     let mut row: Vec<U8Vec> = header_contents.iter().map(|_| Vec::new()).collect();
 
     let mut index: u32 = 0;
     let mut row_count: u32 = 0;
+    let annotation = AnnotationLayout::from_header(vcf_reader.header());
+
+    let select_annotation = header_contents
+        .iter()
+        .any(|x| matches!(x, HeaderType::AnnotationField(..)));
+
+    let inheritance_column = header_contents
+        .iter()
+        .position(|x| *x == HeaderType::Inheritance);
+    // Compound heterozygous candidates are buffered per contig and gene, and resolved when either changes
+    let defer_rows = config
+        .inheritance
+        .as_ref()
+        .map(|x| x.has_compound_heterozygous())
+        .unwrap_or(false);
+    let mut deferred: Vec<DeferredRow> = Vec::new();
+    let mut deferred_key: Option<(U8Vec, Option<U8Vec>)> = None;
 
     let mut record = VCFRecord::new(vcf_reader.header().clone());
     while vcf_reader.next_record(&mut record)? {
         index += 1;
 
-        if config.split_multi_allelic {
-            for (alt_index, _) in record.alternative.iter().enumerate() {
-                if !writer.is_next_row_allowed() {
+        let alt_indexes: Vec<Option<usize>> = if config.split_multi_allelic {
+            (0..record.alternative.len()).map(Some).collect()
+        } else {
+            vec![None]
+        };
+
+        for alt_index in alt_indexes {
+            if !config
+                .genotype_filters
+                .iter()
+                .all(|x| x.matches(&record, alt_index))
+            {
+                continue;
+            }
+            let inheritance_match = config
+                .inheritance
+                .as_ref()
+                .map(|x| x.matches(&record, alt_index));
+            if inheritance_match.as_ref().map(|x| x.is_empty()) == Some(true) {
+                continue;
+            }
+            let gene = match annotation.as_ref() {
+                Some(layout) if defer_rows => layout
+                    .select(
+                        &record,
+                        alt_index,
+                        config.annotation_transcript,
+                        config.canonical_list.as_ref(),
+                    )?
+                    .map(|x| layout.gene(&x).to_vec()),
+                _ => None,
+            };
+            if defer_rows {
+                let key = (record.chromosome.clone(), gene.clone());
+                if deferred_key.as_ref() != Some(&key) {
+                    if !write_deferred_rows(
+                        &mut deferred,
+                        inheritance_column,
+                        &mut writer,
+                        &mut row_count,
+                    )? {
+                        return Ok(row_count);
+                    }
+                    deferred_key = Some(key);
+                }
+            }
+
+            // One row per annotation in split mode, otherwise one row with the selected annotation
+            let annotations: Vec<(Option<usize>, Option<Annotation>)> = match annotation.as_ref() {
+                Some(layout) if config.split_annotations => {
+                    let annotations = layout.annotations(&record, alt_index);
+                    if annotations.is_empty() {
+                        vec![(None, None)]
+                    } else {
+                        annotations
+                            .into_iter()
+                            .enumerate()
+                            .map(|(i, x)| (Some(i), Some(x)))
+                            .collect()
+                    }
+                }
+                Some(layout) if select_annotation => vec![(
+                    None,
+                    layout.select(
+                        &record,
+                        alt_index,
+                        config.annotation_transcript,
+                        config.canonical_list.as_ref(),
+                    )?,
+                )],
+                _ => vec![(None, None)],
+            };
+
+            for (annotation_index, selected) in annotations.iter() {
+                if !defer_rows && !writer.is_next_row_allowed() {
                     eprintln!("WARNING: The output of VCF table is truncated");
                     return Ok(row_count);
                 }
 
                 setup_row(
+                    annotation.as_ref(),
                     group_name,
                     header_contents,
                     &record,
                     &mut row,
                     index,
-                    Some(alt_index),
+                    alt_index,
                     config.decoded_genotype,
                     config.canonical_list.as_ref(),
+                    selected.as_ref(),
+                    *annotation_index,
                 )?;
+                if defer_rows {
+                    deferred.push((
+                        row.clone(),
+                        gene.clone(),
+                        inheritance_match.clone().unwrap_or_default(),
+                    ));
+                    continue;
+                }
+                if let (Some(column), Some(matched)) =
+                    (inheritance_column, inheritance_match.as_ref())
+                {
+                    write_inheritance_models(&mut row[column], &matched.models);
+                }
                 writer.write_row_bytes(&row.iter().map(|x| -> &[u8] { &x }).collect::<Vec<_>>())?;
                 row_count += 1;
             }
-        } else {
-            if !writer.is_next_row_allowed() {
-                eprintln!("WARNING: The output of VCF table is truncated");
-                return Ok(row_count);
-            }
-
-            setup_row(
-                group_name,
-                header_contents,
-                &record,
-                &mut row,
-                index,
-                None,
-                config.decoded_genotype,
-                config.canonical_list.as_ref(),
-            )?;
-            writer.write_row_bytes(&row.iter().map(|x| -> &[u8] { &x }).collect::<Vec<_>>())?;
-            row_count += 1;
         }
     }
 
+    if defer_rows {
+        write_deferred_rows(
+            &mut deferred,
+            inheritance_column,
+            &mut writer,
+            &mut row_count,
+        )?;
+    }
+
     Ok(row_count)
 }
 
+// Resolves compound heterozygous variants among buffered rows of one gene and writes them.
+// Returns false if the output is truncated.
+fn write_deferred_rows<W: TableWriter>(
+    deferred: &mut Vec<DeferredRow>,
+    inheritance_column: Option<usize>,
+    writer: &mut W,
+    row_count: &mut u32,
+) -> anyhow::Result<bool> {
+    let compound = genotype::compound_heterozygous(
+        &deferred
+            .iter()
+            .map(|(_, gene, matched)| (gene.as_deref(), &matched.compound_candidates[..]))
+            .collect::<Vec<_>>(),
+    );
+    for ((mut row, _, mut matched), is_compound) in deferred.drain(..).zip(compound) {
+        if is_compound {
+            matched.models.push(InheritanceModel::CompoundHeterozygous);
+        }
+        if matched.models.is_empty() {
+            continue;
+        }
+        if !writer.is_next_row_allowed() {
+            eprintln!("WARNING: The output of VCF table is truncated");
+            return Ok(false);
+        }
+        if let Some(column) = inheritance_column {
+            write_inheritance_models(&mut row[column], &matched.models);
+        }
+        writer.write_row_bytes(&row.iter().map(|x| -> &[u8] { &x }).collect::<Vec<_>>())?;
+        *row_count += 1;
+    }
+    Ok(true)
+}
+
+fn write_inheritance_models(column: &mut U8Vec, models: &[InheritanceModel]) {
+    column.clear();
+    for (i, model) in models.iter().enumerate() {
+        if i > 0 {
+            column.extend_from_slice(b", ");
+        }
+        column.extend_from_slice(model.to_str().as_bytes());
+    }
+}
+
 pub fn merge_header_contents(original: &[HeaderType], new: &[HeaderType]) -> Vec<HeaderType> {
     let mut merged = Vec::new();
 
@@ -976,5 +1236,22 @@ pub fn merge_header_contents(original: &[HeaderType], new: &[HeaderType]) -> Vec
     merged
 }
 
+/// Combines headers of multiple VCFs. Columns not found in earlier headers are appended.
+pub fn union_header_contents<'a>(
+    headers: impl IntoIterator<Item = &'a [HeaderType]>,
+) -> Vec<HeaderType> {
+    let mut union: Vec<HeaderType> = Vec::new();
+    for one in headers {
+        let matched = merge_header_contents(one, &union);
+        union.extend(
+            one.iter()
+                .zip(matched)
+                .filter(|(_, y)| *y == HeaderType::Empty)
+                .map(|(x, _)| x.clone()),
+        );
+    }
+    union
+}
+
 #[cfg(test)]
 mod test;
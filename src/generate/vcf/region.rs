@@ -0,0 +1,216 @@
+use super::RecordSource;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::BufRead;
+use vcf::{U8Vec, VCFHeader, VCFRecord};
+
+/// 1-based closed interval
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub chrom: U8Vec,
+    pub start: u64,
+    pub end: u64,
+}
+
+fn parse_position(text: &str) -> Result<u64> {
+    text.trim()
+        .replace(',', "")
+        .parse()
+        .with_context(|| format!("Invalid position: {}", text))
+}
+
+impl Region {
+    /// Parses `chr1`, `chr1:100` or `chr1:100-200`
+    pub fn parse(text: &str) -> Result<Self> {
+        let text = text.trim();
+        let (chrom, range) = match text.rsplit_once(':') {
+            Some((chrom, range)) => (chrom, Some(range)),
+            None => (text, None),
+        };
+        if chrom.is_empty() {
+            return Err(anyhow::anyhow!("Invalid region: {}", text));
+        }
+        let (start, end) = match range {
+            None => (1, u64::MAX),
+            Some(range) => match range.split_once('-') {
+                Some((start, end)) => (parse_position(start)?, parse_position(end)?),
+                None => {
+                    let position = parse_position(range)?;
+                    (position, position)
+                }
+            },
+        };
+        if start == 0 || start > end {
+            return Err(anyhow::anyhow!("Invalid region: {}", text));
+        }
+        Ok(Region {
+            chrom: chrom.as_bytes().to_vec(),
+            start,
+            end,
+        })
+    }
+}
+
+pub fn load_bed(reader: impl BufRead) -> Result<Vec<Region>> {
+    let mut regions = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        let columns: Vec<_> = line.split('\t').collect();
+        if columns.len() < 3 {
+            return Err(anyhow::anyhow!("Invalid BED line at {}: {}", i + 1, line));
+        }
+        // BED is 0-based and half-open
+        let start = parse_position(columns[1])?;
+        let end = parse_position(columns[2])?;
+        if start >= end {
+            continue;
+        }
+        regions.push(Region {
+            chrom: columns[0].as_bytes().to_vec(),
+            start: start + 1,
+            end,
+        });
+    }
+    Ok(regions)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RegionSet {
+    regions: HashMap<U8Vec, Vec<(u64, u64)>>,
+}
+
+impl RegionSet {
+    pub fn new(regions: &[Region]) -> Self {
+        let mut grouped: HashMap<U8Vec, Vec<(u64, u64)>> = HashMap::new();
+        for one in regions {
+            grouped
+                .entry(one.chrom.clone())
+                .or_default()
+                .push((one.start, one.end));
+        }
+        for intervals in grouped.values_mut() {
+            intervals.sort_unstable();
+            let mut merged: Vec<(u64, u64)> = Vec::new();
+            for (start, end) in intervals.iter() {
+                match merged.last_mut() {
+                    Some(last) if *start <= last.1.saturating_add(1) => last.1 = last.1.max(*end),
+                    _ => merged.push((*start, *end)),
+                }
+            }
+            *intervals = merged;
+        }
+        RegionSet { regions: grouped }
+    }
+
+    /// Merged intervals of a chromosome in ascending order
+    pub fn intervals(&self, chrom: &[u8]) -> &[(u64, u64)] {
+        self.regions.get(chrom).map(|x| &x[..]).unwrap_or(&[])
+    }
+
+    /// Chromosomes with at least one region in sorted order
+    pub fn chromosomes(&self) -> Vec<&[u8]> {
+        let mut chromosomes: Vec<_> = self.regions.keys().map(|x| &x[..]).collect();
+        chromosomes.sort_unstable();
+        chromosomes
+    }
+
+    pub fn overlaps(&self, record: &VCFRecord) -> bool {
+        let intervals = self.intervals(&record.chromosome);
+        let start = record.position;
+        let end = start + (record.reference.len().max(1) as u64) - 1;
+        let index = intervals.partition_point(|x| x.1 < start);
+        intervals.get(index).map(|x| x.0 <= end).unwrap_or(false)
+    }
+}
+
+/// Scans all records and keeps the ones overlapping the regions
+pub struct RegionFilter<S: RecordSource> {
+    inner: S,
+    regions: RegionSet,
+}
+
+impl<S: RecordSource> RegionFilter<S> {
+    pub fn new(inner: S, regions: RegionSet) -> Self {
+        RegionFilter { inner, regions }
+    }
+}
+
+impl<S: RecordSource> RecordSource for RegionFilter<S> {
+    fn header(&self) -> &VCFHeader {
+        self.inner.header()
+    }
+
+    fn next_record(&mut self, record: &mut VCFRecord) -> Result<bool> {
+        while self.inner.next_record(record)? {
+            if self.regions.overlaps(record) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::BufReader;
+
+    #[test]
+    fn test_parse_region() -> Result<()> {
+        assert_eq!(
+            Region::parse("chr1:1,000-2,000")?,
+            Region {
+                chrom: b"chr1".to_vec(),
+                start: 1000,
+                end: 2000
+            }
+        );
+        assert_eq!(
+            Region::parse("13")?,
+            Region {
+                chrom: b"13".to_vec(),
+                start: 1,
+                end: u64::MAX
+            }
+        );
+        assert_eq!(Region::parse("HLA-A*01:01:10")?.start, 10);
+        assert!(Region::parse("chr1:200-100").is_err());
+        assert!(Region::parse("chr1:0").is_err());
+        assert!(Region::parse(":100").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_region_filter() -> Result<()> {
+        let regions = load_bed(
+            &b"track name=panel\n13\t32889967\t32889968\tBRCA2\n13\t32918300\t32918305\n17\t0\t10\n"[..],
+        )?;
+        assert_eq!(regions.len(), 3);
+        assert_eq!(regions[0].start, 32889968);
+        assert_eq!(
+            RegionSet::new(&regions).chromosomes(),
+            vec![&b"13"[..], &b"17"[..]]
+        );
+
+        let vcf_data = include_bytes!("../../../examples/vcf/simple1.vcf");
+        let mut vcf_data_reader = BufReader::new(&vcf_data[..]);
+        let mut reader = RegionFilter::new(
+            vcf::VCFReader::new(&mut vcf_data_reader)?,
+            RegionSet::new(&regions),
+        );
+        let mut record = VCFRecord::new(reader.header().clone());
+        let mut positions = Vec::new();
+        while reader.next_record(&mut record)? {
+            positions.push(record.position);
+        }
+        assert_eq!(positions, vec![32889968, 32918303]);
+        Ok(())
+    }
+}
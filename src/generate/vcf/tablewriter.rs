@@ -1,12 +1,15 @@
+use super::super::autofit::ColumnWidths;
+use super::super::comment::write_comment;
+use super::super::link::ColumnLink;
+use super::super::overflow::{row_limit_error, OverflowSheets, XLSX_MAX_ROW};
 use super::super::FormatManager;
+use crate::model::{Comment, OverflowPolicy};
 use anyhow::Result;
 use std::collections::{HashMap, HashSet};
 use std::io::Write;
 use std::str;
 use xlsxwriter::worksheet::{WorksheetCol, WorksheetRow};
 
-const XLSX_MAX_ROW: WorksheetRow = 1048576;
-
 pub trait TableWriter {
     fn set_header(&mut self, items: &[String]);
     fn header(&self) -> &[String];
@@ -78,6 +81,9 @@ impl<T: TableWriter + ?Sized> TableWriter for Box<T> {
     fn column_widths(&mut self, widths: &[f64]) -> Result<()> {
         (**self).column_widths(widths)
     }
+    fn is_next_row_allowed(&self) -> bool {
+        (**self).is_next_row_allowed()
+    }
 }
 
 impl<T: TableWriter + ?Sized> TableWriter for &mut T {
@@ -105,6 +111,9 @@ impl<T: TableWriter + ?Sized> TableWriter for &mut T {
     fn column_widths(&mut self, widths: &[f64]) -> Result<()> {
         (**self).column_widths(widths)
     }
+    fn is_next_row_allowed(&self) -> bool {
+        (**self).is_next_row_allowed()
+    }
 }
 
 #[derive(Debug)]
@@ -189,10 +198,18 @@ pub struct XlsxSheetWriter<'a, 'b> {
     pub header_comment: Vec<String>,
     data_type: Vec<XlsxDataType>,
     current_row: WorksheetRow,
+    offset_row: WorksheetRow,
     offset_col: WorksheetCol,
     column_filter_index: Option<usize>,
     column_filter_list: &'a HashSet<String>,
     format_manager: &'a FormatManager,
+    overflow_policy: OverflowPolicy,
+    overflow_sheets: Option<&'a mut OverflowSheets<'b>>,
+    sheet_last_rows: Vec<WorksheetRow>,
+    links: Vec<Option<ColumnLink>>,
+    comment_sources: Vec<Option<usize>>,
+    written_rows: usize,
+    auto_fit: Option<&'a mut ColumnWidths>,
 }
 
 impl<'a, 'b> XlsxSheetWriter<'a, 'b> {
@@ -210,18 +227,76 @@ impl<'a, 'b> XlsxSheetWriter<'a, 'b> {
             header_comment: Vec::new(),
             data_type: Vec::new(),
             current_row: offset_row,
+            offset_row,
             offset_col,
             column_filter_index,
             column_filter_list,
             format_manager,
+            overflow_policy: OverflowPolicy::Truncate,
+            overflow_sheets: None,
+            sheet_last_rows: Vec::new(),
+            links: Vec::new(),
+            comment_sources: Vec::new(),
+            written_rows: 0,
+            auto_fit: None,
         }
     }
 
+    pub fn set_overflow(
+        &mut self,
+        overflow_policy: OverflowPolicy,
+        overflow_sheets: &'a mut OverflowSheets<'b>,
+    ) {
+        self.overflow_policy = overflow_policy;
+        self.overflow_sheets = Some(overflow_sheets);
+    }
+
+    pub fn sheet_last_rows(&self) -> Vec<WorksheetRow> {
+        let mut last_rows = self.sheet_last_rows.clone();
+        last_rows.push(self.current_row.saturating_sub(1));
+        last_rows
+    }
+
+    fn current_sheet(&mut self) -> &mut xlsxwriter::Worksheet<'b> {
+        let continued = !self.sheet_last_rows.is_empty();
+        match self.overflow_sheets.as_deref_mut().filter(|_| continued) {
+            Some(sheets) => sheets.last_mut().unwrap(),
+            None => self.writer,
+        }
+    }
+
+    fn start_next_sheet(&mut self) -> Result<()> {
+        let sheets = self
+            .overflow_sheets
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("No workbook to add overflow sheets"))?;
+        sheets.add_sheet()?;
+        self.sheet_last_rows.push(self.current_row - 1);
+        self.current_row = self.offset_row;
+        self.write_header()
+    }
+
     pub fn set_data_type(&mut self, data_type: &[XlsxDataType]) {
         self.data_type.clear();
         self.data_type.extend_from_slice(data_type);
     }
 
+    pub fn set_auto_fit(&mut self, widths: &'a mut ColumnWidths) {
+        self.auto_fit = Some(widths);
+    }
+
+    pub fn written_rows(&self) -> usize {
+        self.written_rows
+    }
+
+    pub fn set_links(&mut self, links: Vec<Option<ColumnLink>>) {
+        self.links = links;
+    }
+
+    pub fn set_comment_sources(&mut self, comment_sources: Vec<Option<usize>>) {
+        self.comment_sources = comment_sources;
+    }
+
     pub fn set_header_comment(&mut self, items: &[String]) {
         self.header_comment.clear();
         self.header_comment.extend_from_slice(items);
@@ -247,16 +322,20 @@ impl<'a, 'b> TableWriter for XlsxSheetWriter<'a, 'b> {
 
     fn write_header(&mut self) -> Result<()> {
         let header: Vec<_> = self.header().iter().map(|x| x.to_string()).collect();
+        let header_comment = self.header_comment.clone();
+        let row = self.current_row;
+        let offset_col = self.offset_col;
+        if let Some(auto_fit) = self.auto_fit.as_deref_mut() {
+            for (i, column) in header.iter().enumerate() {
+                auto_fit.record_text(i as u16 + offset_col, column, None, true);
+            }
+        }
+        let sheet = self.current_sheet();
         for (i, column) in header.iter().enumerate() {
-            self.writer
-                .write_string(self.current_row, i as u16 + self.offset_col, column, None)?;
-            if let Some(comment) = self.header_comment.get(i) {
+            sheet.write_string(row, i as u16 + offset_col, column, None)?;
+            if let Some(comment) = header_comment.get(i) {
                 if comment != "" {
-                    self.writer.write_comment(
-                        self.current_row,
-                        i as u16 + self.offset_col,
-                        &comment,
-                    )?;
+                    sheet.write_comment(row, i as u16 + offset_col, comment)?;
                 }
             }
         }
@@ -265,43 +344,91 @@ impl<'a, 'b> TableWriter for XlsxSheetWriter<'a, 'b> {
     }
 
     fn write_row(&mut self, items: &[&str]) -> Result<()> {
+        if self.current_row >= XLSX_MAX_ROW {
+            match self.overflow_policy {
+                OverflowPolicy::NewSheet => self.start_next_sheet()?,
+                _ => return Err(row_limit_error()),
+            }
+        }
+        let row = self.current_row;
+        let offset_col = self.offset_col;
+        let hide_row = self
+            .column_filter_index
+            .map(|i| items.get(i))
+            .flatten()
+            .map(|x| !self.column_filter_list.contains(*x))
+            .unwrap_or(false);
+        let continued = !self.sheet_last_rows.is_empty();
+        let sheet = match self.overflow_sheets.as_deref_mut().filter(|_| continued) {
+            Some(sheets) => sheets.last_mut().unwrap(),
+            None => &mut *self.writer,
+        };
+        let data_type_list = &self.data_type;
+        let format_manager = self.format_manager;
+        if hide_row {
+            sheet.set_row_opt(
+                row,
+                xlsxwriter::worksheet::LXW_DEF_ROW_HEIGHT,
+                None,
+                &xlsxwriter::worksheet::RowColOptions::new(true, 0, false),
+            )?;
+        }
         for (i, column) in items.iter().enumerate() {
-            let write_col = i as WorksheetCol + self.offset_col;
+            let write_col = i as WorksheetCol + offset_col;
+            if let Some(comment) = self
+                .comment_sources
+                .get(i)
+                .copied()
+                .flatten()
+                .and_then(|x| items.get(x))
+                .filter(|x| !x.is_empty())
+            {
+                write_comment(sheet, row, write_col, &Comment::Text(comment.to_string()))?;
+            }
             if column.is_empty() {
-                self.writer.write_blank(self.current_row, i as u16, None)?;
+                sheet.write_blank(row, write_col, None)?;
+            } else if let Some(link) = self.links.get(i).and_then(|x| x.as_ref()) {
+                let format = format_manager.get_format(None, super::super::CellType::Url);
+                sheet.write_url(row, write_col, &link.url.render(items), format)?;
+                let text = link.text.as_ref().map(|x| x.render(items));
+                let text = text.as_deref().unwrap_or(column);
+                sheet.write_string(row, write_col, text, format)?;
+                if let Some(auto_fit) = self.auto_fit.as_deref_mut() {
+                    auto_fit.record_text(write_col, text, None, false);
+                }
             } else {
-                let data_type = self
-                    .data_type
+                if let Some(auto_fit) = self.auto_fit.as_deref_mut() {
+                    auto_fit.record_text(write_col, column, None, false);
+                }
+                let data_type = data_type_list
                     .get(i)
                     .copied()
                     .unwrap_or(XlsxDataType::String);
                 match data_type {
                     XlsxDataType::String => {
                         if column.len() > 32766 {
-                            self.writer.write_string(
-                                self.current_row,
+                            sheet.write_string(
+                                row,
                                 write_col,
                                 &format!("{}...", &column[0..32763]),
-                                self.format_manager
-                                    .get_format(None, super::super::CellType::String),
+                                format_manager.get_format(None, super::super::CellType::String),
                             )?;
                         } else {
-                            self.writer.write_string(
-                                self.current_row,
+                            sheet.write_string(
+                                row,
                                 write_col,
                                 column,
-                                self.format_manager
-                                    .get_format(None, super::super::CellType::String),
+                                format_manager.get_format(None, super::super::CellType::String),
                             )?;
                         }
                     }
                     XlsxDataType::Number | XlsxDataType::Integer => {
                         if let Ok(f) = column.parse() {
-                            self.writer.write_number(
-                                self.current_row,
+                            sheet.write_number(
+                                row,
                                 write_col,
                                 f,
-                                self.format_manager.get_format(
+                                format_manager.get_format(
                                     None,
                                     match data_type {
                                         XlsxDataType::Integer => super::super::CellType::Integer,
@@ -310,44 +437,28 @@ impl<'a, 'b> TableWriter for XlsxSheetWriter<'a, 'b> {
                                 ),
                             )?;
                         } else {
-                            self.writer.write_string(
-                                self.current_row,
+                            sheet.write_string(
+                                row,
                                 write_col,
                                 column,
-                                self.format_manager
-                                    .get_format(None, super::super::CellType::String),
+                                format_manager.get_format(None, super::super::CellType::String),
                             )?;
                         }
                     }
                     XlsxDataType::Boolean => {
-                        self.writer.write_boolean(
-                            self.current_row,
+                        sheet.write_boolean(
+                            row,
                             write_col,
                             *column == "TRUE" || *column == "True" || *column == "true",
-                            self.format_manager
-                                .get_format(None, super::super::CellType::Boolean),
+                            format_manager.get_format(None, super::super::CellType::Boolean),
                         )?;
                     }
                 }
             }
         }
 
-        if self
-            .column_filter_index
-            .map(|i| items.get(i))
-            .flatten()
-            .map(|x| !self.column_filter_list.contains(*x))
-            .unwrap_or(false)
-        {
-            self.writer.set_row_opt(
-                self.current_row,
-                xlsxwriter::worksheet::LXW_DEF_ROW_HEIGHT,
-                None,
-                &xlsxwriter::worksheet::RowColOptions::new(true, 0, false),
-            )?;
-        }
-
         self.current_row += 1;
+        self.written_rows += 1;
         Ok(())
     }
 
@@ -364,7 +475,7 @@ impl<'a, 'b> TableWriter for XlsxSheetWriter<'a, 'b> {
     }
 
     fn is_next_row_allowed(&self) -> bool {
-        self.current_row < XLSX_MAX_ROW
+        self.overflow_policy != OverflowPolicy::Truncate || self.current_row < XLSX_MAX_ROW
     }
 }
 
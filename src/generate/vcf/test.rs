@@ -1,6 +1,7 @@
 use super::super::{FormatManager, EMPTY_FORMAT};
 use super::tablewriter;
 use super::*;
+use std::collections::HashMap;
 use std::io::BufReader;
 
 #[test]
@@ -74,11 +75,18 @@ fn test_vcf2table_csv_split_multi() -> Result<()> {
         format_list: vec![b"AD".to_vec(), b"DP".to_vec(), b"GT".to_vec()],
         replace_sample_name: None,
         group_names: None,
+        annotation_fields: vec![],
+        annotation_transcript: AnnotationTranscript::MostSevere,
+        split_annotations: false,
+        samples: None,
+        missing_field: MissingFieldPolicy::Error,
+        genotype_filters: vec![],
+        inheritance: None,
     };
     let mut vcf_data_reader = BufReader::new(&vcf_data[..]);
     let mut vcf_reader = vcf::VCFReader::new(&mut vcf_data_reader)?;
     let mut write_bytes = Vec::<u8>::new();
-    let header_contents = create_header_line(&vcf_reader.header(), &config);
+    let header_contents = create_header_line(&vcf_reader.header(), &config)?;
     vcf2table(
         &mut vcf_reader,
         &header_contents,
@@ -116,11 +124,18 @@ fn test_vcf2table_csv_split_multi_snpeff() -> Result<()> {
         format_list: vec![b"AD".to_vec(), b"DP".to_vec(), b"GT".to_vec()],
         replace_sample_name: None,
         group_names: None,
+        annotation_fields: vec![],
+        annotation_transcript: AnnotationTranscript::MostSevere,
+        split_annotations: false,
+        samples: None,
+        missing_field: MissingFieldPolicy::Error,
+        genotype_filters: vec![],
+        inheritance: None,
     };
     let mut vcf_data_reader = BufReader::new(&vcf_data[..]);
     let mut vcf_reader = vcf::VCFReader::new(&mut vcf_data_reader)?;
     let mut write_bytes = Vec::<u8>::new();
-    let header_contents = create_header_line(&vcf_reader.header(), &config);
+    let header_contents = create_header_line(&vcf_reader.header(), &config)?;
     vcf2table(
         &mut vcf_reader,
         &header_contents,
@@ -137,6 +152,52 @@ fn test_vcf2table_csv_split_multi_snpeff() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_vcf2table_csv_split_multi_snpeff_bcf() -> Result<()> {
+    let bcf_data = include_bytes!("../../../examples/vcf/simple1-snpeff.bcf");
+    let config = VCF2CSVConfig {
+        split_multi_allelic: true,
+        decoded_genotype: true,
+        canonical_list: None,
+        priority_info_list: vec![],
+        priority_format_list: vec![],
+        info_list: vec![
+            b"AC".to_vec(),
+            b"AF".to_vec(),
+            b"AN".to_vec(),
+            b"DP".to_vec(),
+            b"ANN".to_vec(),
+            b"FLAG".to_vec(),
+        ],
+        format_list: vec![b"AD".to_vec(), b"DP".to_vec(), b"GT".to_vec()],
+        replace_sample_name: None,
+        group_names: None,
+        annotation_fields: vec![],
+        annotation_transcript: AnnotationTranscript::MostSevere,
+        split_annotations: false,
+        samples: None,
+        missing_field: MissingFieldPolicy::Error,
+        genotype_filters: vec![],
+        inheritance: None,
+    };
+    let mut bcf_reader = bcf::BcfReader::new(flate2::read::MultiGzDecoder::new(&bcf_data[..]))?;
+    let mut write_bytes = Vec::<u8>::new();
+    let header_contents = create_header_line(bcf_reader.header(), &config)?;
+    vcf2table(
+        &mut bcf_reader,
+        &header_contents,
+        &config,
+        None,
+        true,
+        &mut tablewriter::CSVWriter::new(&mut write_bytes),
+    )?;
+    assert_eq!(
+        &write_bytes[..],
+        &include_bytes!("../../../examples/vcf/simple1-expected-multiallelic-split-snpeff.csv")[..]
+    );
+    Ok(())
+}
+
 #[test]
 fn test_vcf2table_csv_split_multi_snpeff_with_canonical() -> Result<()> {
     let vcf_data = include_bytes!("../../../examples/vcf/1kGP-subset-snpeff.vcf");
@@ -161,11 +222,18 @@ fn test_vcf2table_csv_split_multi_snpeff_with_canonical() -> Result<()> {
         format_list: vec![b"AD".to_vec(), b"DP".to_vec(), b"GT".to_vec()],
         replace_sample_name: None,
         group_names: None,
+        annotation_fields: vec![],
+        annotation_transcript: AnnotationTranscript::MostSevere,
+        split_annotations: false,
+        samples: None,
+        missing_field: MissingFieldPolicy::Error,
+        genotype_filters: vec![],
+        inheritance: None,
     };
     let mut vcf_data_reader = BufReader::new(&vcf_data[..]);
     let mut vcf_reader = vcf::VCFReader::new(&mut vcf_data_reader)?;
     let mut write_bytes = Vec::<u8>::new();
-    let header_contents = create_header_line(&vcf_reader.header(), &config);
+    let header_contents = create_header_line(&vcf_reader.header(), &config)?;
     vcf2table(
         &mut vcf_reader,
         &header_contents,
@@ -182,6 +250,42 @@ fn test_vcf2table_csv_split_multi_snpeff_with_canonical() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_create_header_line_canonical_without_list() -> Result<()> {
+    let vcf_data = include_bytes!("../../../examples/vcf/1kGP-subset-snpeff.vcf");
+    let mut config = VCF2CSVConfig {
+        split_multi_allelic: true,
+        decoded_genotype: false,
+        canonical_list: None,
+        priority_info_list: vec![],
+        priority_format_list: vec![],
+        info_list: vec![],
+        format_list: vec![],
+        replace_sample_name: None,
+        group_names: None,
+        annotation_fields: vec!["Gene_Name".to_string()],
+        annotation_transcript: AnnotationTranscript::Canonical,
+        split_annotations: false,
+        samples: None,
+        missing_field: MissingFieldPolicy::Error,
+        genotype_filters: vec![],
+        inheritance: None,
+    };
+    let mut vcf_data_reader = BufReader::new(&vcf_data[..]);
+    let vcf_reader = vcf::VCFReader::new(&mut vcf_data_reader)?;
+    // SnpEff output has no canonical flags
+    assert!(create_header_line(vcf_reader.header(), &config).is_err());
+
+    config.canonical_list = Some(
+        [b"ENST00000380152.7_1"]
+            .iter()
+            .map(|x| x.to_vec())
+            .collect(),
+    );
+    create_header_line(vcf_reader.header(), &config)?;
+    Ok(())
+}
+
 #[test]
 fn test_vcf2table_csv_no_split() -> Result<()> {
     let vcf_data = include_bytes!("../../../examples/vcf/simple1.vcf");
@@ -200,11 +304,18 @@ fn test_vcf2table_csv_no_split() -> Result<()> {
         format_list: vec![b"AD".to_vec(), b"DP".to_vec(), b"GT".to_vec()],
         replace_sample_name: None,
         group_names: None,
+        annotation_fields: vec![],
+        annotation_transcript: AnnotationTranscript::MostSevere,
+        split_annotations: false,
+        samples: None,
+        missing_field: MissingFieldPolicy::Error,
+        genotype_filters: vec![],
+        inheritance: None,
     };
     let mut vcf_data_reader = BufReader::new(&vcf_data[..]);
     let mut vcf_reader = vcf::VCFReader::new(&mut vcf_data_reader)?;
     let mut write_bytes = Vec::<u8>::new();
-    let header_contents = create_header_line(&vcf_reader.header(), &config);
+    let header_contents = create_header_line(&vcf_reader.header(), &config)?;
     vcf2table(
         &mut vcf_reader,
         &header_contents,
@@ -238,6 +349,13 @@ fn test_vcf2table_xlsx_split_multi() -> Result<()> {
         format_list: vec![b"GT".to_vec(), b"AD".to_vec(), b"DP".to_vec()],
         replace_sample_name: None,
         group_names: None,
+        annotation_fields: vec![],
+        annotation_transcript: AnnotationTranscript::MostSevere,
+        split_annotations: false,
+        samples: None,
+        missing_field: MissingFieldPolicy::Error,
+        genotype_filters: vec![],
+        inheritance: None,
     };
     let mut vcf_data_reader = BufReader::new(&vcf_data[..]);
     let mut vcf_reader = vcf::VCFReader::new(&mut vcf_data_reader)?;
@@ -248,7 +366,7 @@ fn test_vcf2table_xlsx_split_multi() -> Result<()> {
     let empty_hash = HashSet::new();
     let mut writer =
         tablewriter::XlsxSheetWriter::new(&mut sheet, &format_manager, 2, 3, None, &empty_hash);
-    let header_contents = create_header_line(&vcf_reader.header(), &config);
+    let header_contents = create_header_line(&vcf_reader.header(), &config)?;
     vcf2table_set_data_type(&header_contents, &mut writer)?;
     let row = vcf2table(
         &mut vcf_reader,
@@ -281,6 +399,13 @@ fn test_vcf2table_xlsx_no_split_multi() -> Result<()> {
         format_list: vec![b"GT".to_vec(), b"AD".to_vec(), b"DP".to_vec()],
         replace_sample_name: None,
         group_names: None,
+        annotation_fields: vec![],
+        annotation_transcript: AnnotationTranscript::MostSevere,
+        split_annotations: false,
+        samples: None,
+        missing_field: MissingFieldPolicy::Error,
+        genotype_filters: vec![],
+        inheritance: None,
     };
     let mut vcf_data_reader = BufReader::new(&vcf_data[..]);
     let mut vcf_reader = vcf::VCFReader::new(&mut vcf_data_reader)?;
@@ -291,7 +416,7 @@ fn test_vcf2table_xlsx_no_split_multi() -> Result<()> {
     let empty_hash = HashSet::new();
     let mut writer =
         tablewriter::XlsxSheetWriter::new(&mut sheet, &format_manager, 0, 0, None, &empty_hash);
-    let header_contents = create_header_line(&vcf_reader.header(), &config);
+    let header_contents = create_header_line(&vcf_reader.header(), &config)?;
     vcf2table_set_data_type(&header_contents, &mut writer)?;
     let row = vcf2table(
         &mut vcf_reader,
@@ -324,6 +449,13 @@ fn test_vcf2table_xlsx_split_multi_with_group_name() -> Result<()> {
         format_list: vec![b"GT".to_vec(), b"AD".to_vec(), b"DP".to_vec()],
         replace_sample_name: Some(vec![b"SAMPLE1".to_vec()]),
         group_names: Some(vec![b"GROUP".to_vec()]),
+        annotation_fields: vec![],
+        annotation_transcript: AnnotationTranscript::MostSevere,
+        split_annotations: false,
+        samples: None,
+        missing_field: MissingFieldPolicy::Error,
+        genotype_filters: vec![],
+        inheritance: None,
     };
     let mut vcf_data_reader = BufReader::new(&vcf_data[..]);
     let mut vcf_reader = vcf::VCFReader::new(&mut vcf_data_reader)?;
@@ -334,7 +466,7 @@ fn test_vcf2table_xlsx_split_multi_with_group_name() -> Result<()> {
     let empty_hash = HashSet::new();
     let mut writer =
         tablewriter::XlsxSheetWriter::new(&mut sheet, &format_manager, 0, 0, None, &empty_hash);
-    let header_contents = create_header_line(&vcf_reader.header(), &config);
+    let header_contents = create_header_line(&vcf_reader.header(), &config)?;
     vcf2table_set_data_type(&header_contents, &mut writer)?;
     let row = vcf2table(
         &mut vcf_reader,
@@ -348,3 +480,573 @@ fn test_vcf2table_xlsx_split_multi_with_group_name() -> Result<()> {
     workbook.close()?;
     Ok(())
 }
+
+#[test]
+fn test_vcf2table_csv_split_multi_vep() -> Result<()> {
+    let vcf_data = include_bytes!("../../../examples/vcf/simple1-vep.vcf");
+    let config = VCF2CSVConfig {
+        split_multi_allelic: true,
+        decoded_genotype: false,
+        canonical_list: None,
+        priority_info_list: vec![],
+        priority_format_list: vec![],
+        info_list: vec![b"CSQ".to_vec()],
+        format_list: vec![],
+        replace_sample_name: None,
+        group_names: None,
+        annotation_fields: vec![],
+        annotation_transcript: AnnotationTranscript::MostSevere,
+        split_annotations: false,
+        samples: None,
+        missing_field: MissingFieldPolicy::Error,
+        genotype_filters: vec![],
+        inheritance: None,
+    };
+    let mut vcf_data_reader = BufReader::new(&vcf_data[..]);
+    let mut vcf_reader = vcf::VCFReader::new(&mut vcf_data_reader)?;
+    let mut write_bytes = Vec::<u8>::new();
+    let header_contents = create_header_line(vcf_reader.header(), &config)?;
+    vcf2table(
+        &mut vcf_reader,
+        &header_contents,
+        &config,
+        None,
+        true,
+        &mut tablewriter::CSVWriter::new(&mut write_bytes),
+    )?;
+    let mut reader = csv::Reader::from_reader(&write_bytes[..]);
+    let header: Vec<_> = reader.headers()?.iter().map(|x| x.to_string()).collect();
+    let rows: Vec<Vec<String>> = reader
+        .records()
+        .map(|x| x.map(|y| y.iter().map(|z| z.to_string()).collect()))
+        .collect::<Result<_, _>>()?;
+    let column = |name: &str| -> Vec<&str> {
+        let index = header.iter().position(|x| x == name).unwrap();
+        rows.iter().map(|x| x[index].as_str()).collect()
+    };
+    assert_eq!(
+        column("Canonical Gene"),
+        vec![
+            "",
+            "",
+            "ENST00000380152(BRCA2):p.Ile605TyrfsTer9",
+            "ENST00000380152(BRCA2):p.Ile605AsnfsTer11"
+        ]
+    );
+    assert_eq!(
+        column("SnpEff Impact"),
+        vec!["MODIFIER", "MODIFIER", "HIGH", "HIGH"]
+    );
+    assert_eq!(
+        column("GeneImpact__MODIFIER"),
+        vec!["BRCA2, ZAR1L", "BRCA2", "BRCA2", ""]
+    );
+    assert_eq!(
+        column("SnpEff")[1],
+        "ENST00000380152(BRCA2):c.67+20_67+21del"
+    );
+    assert_eq!(column("CSQ")[3].split(',').count(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_vcf2table_csv_annotation_fields() -> Result<()> {
+    let vcf_data = include_bytes!("../../../examples/vcf/simple1-snpeff.vcf");
+    let config = VCF2CSVConfig {
+        split_multi_allelic: true,
+        decoded_genotype: false,
+        canonical_list: Some(
+            [b"ENST00000380152.7_1"]
+                .iter()
+                .map(|x| x.to_vec())
+                .collect(),
+        ),
+        priority_info_list: vec![],
+        priority_format_list: vec![],
+        info_list: vec![],
+        format_list: vec![],
+        replace_sample_name: None,
+        group_names: None,
+        annotation_fields: ["Gene_Name", "Feature_ID", "HGVS.c", "Distance", "Unknown"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect(),
+        annotation_transcript: AnnotationTranscript::MostSevere,
+        split_annotations: false,
+        samples: None,
+        missing_field: MissingFieldPolicy::Error,
+        genotype_filters: vec![],
+        inheritance: None,
+    };
+    let mut vcf_data_reader = BufReader::new(&vcf_data[..]);
+    let mut vcf_reader = vcf::VCFReader::new(&mut vcf_data_reader)?;
+    let header_contents = create_header_line(vcf_reader.header(), &config)?;
+    let header: Vec<_> = header_contents.iter().map(|x| x.to_string()).collect();
+    assert_eq!(
+        &header[8..13],
+        &[
+            "SnpEff Impact",
+            "Gene_Name",
+            "Feature_ID",
+            "HGVS.c",
+            "Distance"
+        ]
+    );
+
+    let mut write_bytes = Vec::<u8>::new();
+    vcf2table(
+        &mut vcf_reader,
+        &header_contents,
+        &config,
+        None,
+        true,
+        &mut tablewriter::CSVWriter::new(&mut write_bytes),
+    )?;
+    let mut reader = csv::Reader::from_reader(&write_bytes[..]);
+    let first: Vec<String> = reader
+        .records()
+        .next()
+        .unwrap()?
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+    assert_eq!(
+        &first[9..13],
+        &["ZAR1L", "ENST00000345108.6_2", "c.-3906C>T", "3877"]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_vcf2table_csv_split_annotations() -> Result<()> {
+    let vcf_data = include_bytes!("../../../examples/vcf/simple1-vep.vcf");
+    let config = VCF2CSVConfig {
+        split_multi_allelic: true,
+        decoded_genotype: false,
+        canonical_list: None,
+        priority_info_list: vec![],
+        priority_format_list: vec![],
+        info_list: vec![],
+        format_list: vec![],
+        replace_sample_name: None,
+        group_names: None,
+        annotation_fields: vec![],
+        annotation_transcript: AnnotationTranscript::MostSevere,
+        split_annotations: true,
+        samples: None,
+        missing_field: MissingFieldPolicy::Error,
+        genotype_filters: vec![],
+        inheritance: None,
+    };
+    let mut vcf_data_reader = BufReader::new(&vcf_data[..]);
+    let mut vcf_reader = vcf::VCFReader::new(&mut vcf_data_reader)?;
+    let header_contents = create_header_line(vcf_reader.header(), &config)?;
+    let mut write_bytes = Vec::<u8>::new();
+    let rows = vcf2table(
+        &mut vcf_reader,
+        &header_contents,
+        &config,
+        None,
+        true,
+        &mut tablewriter::CSVWriter::new(&mut write_bytes),
+    )?;
+    assert_eq!(rows, 6);
+
+    let mut reader = csv::Reader::from_reader(&write_bytes[..]);
+    let header: Vec<_> = reader.headers()?.iter().map(|x| x.to_string()).collect();
+    assert_eq!(&header[..4], &["#", "alt #", "annotation #", "CHROM"]);
+    assert!(header.contains(&"Consequence".to_string()));
+    let consequence = header.iter().position(|x| x == "Consequence").unwrap();
+    let rows: Vec<Vec<String>> = reader
+        .records()
+        .map(|x| x.map(|y| y.iter().map(|z| z.to_string()).collect()))
+        .collect::<Result<_, _>>()?;
+    assert_eq!(
+        rows.iter()
+            .map(|x| (x[0].as_str(), x[1].as_str(), x[2].as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("1", "1", "1"),
+            ("1", "1", "2"),
+            ("2", "1", "1"),
+            ("3", "1", "1"),
+            ("3", "1", "2"),
+            ("3", "2", "1"),
+        ]
+    );
+    assert_eq!(rows[1][consequence], "upstream_gene_variant");
+    assert_eq!(rows[4][consequence], "intron_variant");
+    Ok(())
+}
+
+#[test]
+fn test_vcf2table_csv_merge() -> Result<()> {
+    let roles: HashMap<&[u8], &[u8]> = [
+        (&b"SRP150637__HG00099"[..], &b"proband"[..]),
+        (b"SRP150637__HG00100", b"father"),
+        (b"SRP150637__HG00102", b"mother"),
+        (b"SRP150637__HG00104", b"proband"),
+        (b"SRP150637__HG00106", b"mother"),
+    ]
+    .into_iter()
+    .collect();
+    let mut sources = Vec::new();
+    for (vcf_data, group_name) in [
+        (
+            &include_bytes!("../../../examples/vcf/family-a.vcf")[..],
+            b"A",
+        ),
+        (
+            &include_bytes!("../../../examples/vcf/family-b.vcf")[..],
+            b"B",
+        ),
+    ] {
+        let vcf_reader = vcf::VCFReader::new(BufReader::new(vcf_data))?;
+        let config = VCF2CSVConfig {
+            split_multi_allelic: false,
+            decoded_genotype: false,
+            canonical_list: None,
+            priority_info_list: vec![],
+            priority_format_list: vec![],
+            info_list: vec![b"AC".to_vec()],
+            format_list: vec![b"GT".to_vec()],
+            replace_sample_name: Some(
+                vcf_reader
+                    .header()
+                    .samples()
+                    .iter()
+                    .map(|x| roles[&x[..]].to_vec())
+                    .collect(),
+            ),
+            group_names: Some(vec![group_name.to_vec()]),
+            annotation_fields: vec![],
+            annotation_transcript: AnnotationTranscript::MostSevere,
+            split_annotations: false,
+            samples: None,
+            missing_field: MissingFieldPolicy::Error,
+            genotype_filters: vec![],
+            inheritance: None,
+        };
+        let header_contents = create_header_line(vcf_reader.header(), &config)?;
+        sources.push((vcf_reader, config, header_contents, group_name.to_vec()));
+    }
+
+    let header_contents = union_header_contents(sources.iter().map(|x| &x.2[..]));
+    let header: Vec<_> = header_contents.iter().map(|x| x.to_string()).collect();
+    assert_eq!(
+        &header[..],
+        &[
+            "#",
+            "Group Name",
+            "CHROM",
+            "POS",
+            "ID",
+            "REF",
+            "ALT",
+            "SnpEff Impact",
+            "SnpEff",
+            "GeneImpact__HIGH",
+            "GeneImpact__MODERATE",
+            "GeneImpact__LOW",
+            "GeneImpact__MODIFIER",
+            "QUAL",
+            "FILTER",
+            "AC",
+            "proband__GT",
+            "father__GT",
+            "mother__GT"
+        ]
+    );
+
+    let mut write_bytes = Vec::<u8>::new();
+    let mut writer = tablewriter::CSVWriter::new(&mut write_bytes);
+    writer.set_header(&header);
+    writer.write_header()?;
+    for (vcf_reader, config, one, group_name) in sources.iter_mut() {
+        vcf2table(
+            vcf_reader,
+            &merge_header_contents(&header_contents, one),
+            config,
+            Some(group_name),
+            false,
+            &mut writer,
+        )?;
+    }
+
+    drop(writer);
+    let mut reader = csv::Reader::from_reader(&write_bytes[..]);
+    let rows: Vec<Vec<String>> = reader
+        .records()
+        .map(|x| Ok(x?.iter().map(|y| y.to_string()).collect()))
+        .collect::<Result<_>>()?;
+    assert_eq!(rows.len(), 20);
+    assert_eq!(rows[0][1], "A");
+    assert_eq!(&rows[0][16..], &["0/0", "0/1", "0/0"]);
+    assert_eq!(rows[10][1], "B");
+    assert_eq!(&rows[10][16..], &["0/1", "", "1/1"]);
+    Ok(())
+}
+
+#[test]
+fn test_create_header_line_missing_field() -> Result<()> {
+    let vcf_data = include_bytes!("../../../examples/vcf/simple1.vcf");
+    let config = |missing_field| VCF2CSVConfig {
+        split_multi_allelic: false,
+        decoded_genotype: false,
+        canonical_list: None,
+        priority_info_list: vec![],
+        priority_format_list: vec![],
+        info_list: vec![b"AC".to_vec(), b"NOT_FOUND".to_vec()],
+        format_list: vec![b"GT".to_vec(), b"PS".to_vec()],
+        replace_sample_name: None,
+        group_names: None,
+        annotation_fields: vec![],
+        annotation_transcript: AnnotationTranscript::MostSevere,
+        split_annotations: false,
+        samples: None,
+        missing_field,
+        genotype_filters: vec![],
+        inheritance: None,
+    };
+    let mut vcf_data_reader = BufReader::new(&vcf_data[..]);
+    let mut vcf_reader = vcf::VCFReader::new(&mut vcf_data_reader)?;
+
+    let error = create_header_line(vcf_reader.header(), &config(MissingFieldPolicy::Error))
+        .unwrap_err()
+        .to_string();
+    assert_eq!(error, "INFO field \"NOT_FOUND\" is not found in VCF header");
+
+    let header: Vec<_> =
+        create_header_line(vcf_reader.header(), &config(MissingFieldPolicy::Skip))?
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+    assert_eq!(
+        &header[8..],
+        &[
+            "AC",
+            "ERP001775_HiSeq2000_SAMEA1531955-1__GT",
+            "ERP001775_HiSeq2000_SAMEA1531955-2__GT"
+        ]
+    );
+
+    let config = config(MissingFieldPolicy::Empty);
+    let header_contents = create_header_line(vcf_reader.header(), &config)?;
+    let header: Vec<_> = header_contents.iter().map(|x| x.to_string()).collect();
+    assert_eq!(
+        &header[8..],
+        &[
+            "AC",
+            "NOT_FOUND",
+            "ERP001775_HiSeq2000_SAMEA1531955-1__GT",
+            "ERP001775_HiSeq2000_SAMEA1531955-1__PS",
+            "ERP001775_HiSeq2000_SAMEA1531955-2__GT",
+            "ERP001775_HiSeq2000_SAMEA1531955-2__PS"
+        ]
+    );
+    let mut write_bytes = Vec::<u8>::new();
+    vcf2table(
+        &mut vcf_reader,
+        &header_contents,
+        &config,
+        None,
+        true,
+        &mut tablewriter::CSVWriter::new(&mut write_bytes),
+    )?;
+    let mut reader = csv::Reader::from_reader(&write_bytes[..]);
+    let first: Vec<String> = reader
+        .records()
+        .next()
+        .unwrap()?
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+    assert_eq!(&first[8..], &["54", "", "1/1", "", "1/1", ""]);
+    Ok(())
+}
+
+#[test]
+fn test_vcf2table_csv_inheritance() -> Result<()> {
+    let vcf_data = include_bytes!("../../../examples/vcf/trio.vcf");
+    let pedigree = genotype::load_pedigree(&include_bytes!("../../../examples/vcf/trio.ped")[..])?;
+    let mut vcf_data_reader = BufReader::new(&vcf_data[..]);
+    let mut vcf_reader = vcf::VCFReader::new(&mut vcf_data_reader)?;
+    let config = VCF2CSVConfig {
+        split_multi_allelic: false,
+        decoded_genotype: false,
+        canonical_list: None,
+        priority_info_list: vec![],
+        priority_format_list: vec![],
+        info_list: vec![],
+        format_list: vec![b"GT".to_vec()],
+        replace_sample_name: None,
+        group_names: None,
+        annotation_fields: vec![],
+        annotation_transcript: AnnotationTranscript::MostSevere,
+        split_annotations: false,
+        samples: None,
+        missing_field: MissingFieldPolicy::Error,
+        genotype_filters: vec![GenotypeFilter::new(
+            &crate::model::GenotypeFilterDef {
+                sample: "TRIO_CHILD".to_string(),
+                genotype: None,
+                min_dp: Some(10),
+                min_gq: None,
+            },
+            vcf_reader.header(),
+            None,
+        )?],
+        inheritance: Some(InheritanceFilter::new(
+            &pedigree,
+            vcf_reader.header(),
+            None,
+            &[],
+        )?),
+    };
+    let header_contents = create_header_line(vcf_reader.header(), &config)?;
+    let mut write_bytes = Vec::<u8>::new();
+    let row_count = vcf2table(
+        &mut vcf_reader,
+        &header_contents,
+        &config,
+        None,
+        true,
+        &mut tablewriter::CSVWriter::new(&mut write_bytes),
+    )?;
+    assert_eq!(row_count, 4);
+
+    let mut reader = csv::Reader::from_reader(&write_bytes[..]);
+    assert_eq!(
+        &reader.headers()?.iter().collect::<Vec<_>>()[5..7],
+        &["ALT", "Inheritance"]
+    );
+    let rows: Vec<Vec<String>> = reader
+        .records()
+        .map(|x| Ok(x?.iter().map(|y| y.to_string()).collect()))
+        .collect::<Result<_>>()?;
+    let positions: Vec<_> = rows.iter().map(|x| (&x[2][..], &x[6][..])).collect();
+    assert_eq!(
+        positions,
+        vec![
+            ("100", "de-novo, dominant"),
+            ("200", "compound-heterozygous"),
+            ("300", "compound-heterozygous"),
+            ("500", "homozygous-recessive")
+        ]
+    );
+    Ok(())
+}
+
+// Fails like XlsxSheetWriter when more than `max_rows` rows are written
+struct RowLimitWriter {
+    header: Vec<String>,
+    rows: usize,
+    max_rows: usize,
+}
+
+impl tablewriter::TableWriter for RowLimitWriter {
+    fn set_header(&mut self, items: &[String]) {
+        self.header = items.to_vec();
+    }
+    fn header(&self) -> &[String] {
+        &self.header
+    }
+    fn write_row(&mut self, _items: &[&str]) -> Result<()> {
+        if self.rows >= self.max_rows {
+            return Err(anyhow::anyhow!("Row limit exceeded"));
+        }
+        self.rows += 1;
+        Ok(())
+    }
+    fn column_widths(&mut self, _widths: &[f64]) -> Result<()> {
+        Ok(())
+    }
+    fn is_next_row_allowed(&self) -> bool {
+        self.rows < self.max_rows
+    }
+}
+
+#[test]
+fn test_vcf2table_truncate() -> Result<()> {
+    let vcf_data = include_bytes!("../../../examples/vcf/simple1.vcf");
+    let config = VCF2CSVConfig {
+        split_multi_allelic: true,
+        decoded_genotype: false,
+        canonical_list: None,
+        priority_info_list: vec![],
+        priority_format_list: vec![],
+        info_list: vec![],
+        format_list: vec![],
+        replace_sample_name: None,
+        group_names: None,
+        annotation_fields: vec![],
+        annotation_transcript: AnnotationTranscript::MostSevere,
+        split_annotations: false,
+        samples: None,
+        missing_field: MissingFieldPolicy::Error,
+        genotype_filters: vec![],
+        inheritance: None,
+    };
+    let mut vcf_data_reader = BufReader::new(&vcf_data[..]);
+    let mut vcf_reader = vcf::VCFReader::new(&mut vcf_data_reader)?;
+    let header_contents = create_header_line(vcf_reader.header(), &config)?;
+    let mut writer = RowLimitWriter {
+        header: Vec::new(),
+        rows: 0,
+        max_rows: 3,
+    };
+    let rows = vcf2table(
+        &mut vcf_reader,
+        &header_contents,
+        &config,
+        None,
+        true,
+        &mut writer,
+    )?;
+    assert_eq!(rows, 2);
+    assert_eq!(writer.rows, 3);
+    Ok(())
+}
+
+#[test]
+fn test_vcf2table_truncate_split_annotations() -> Result<()> {
+    let vcf_data = include_bytes!("../../../examples/vcf/simple1-vep.vcf");
+    let config = VCF2CSVConfig {
+        split_multi_allelic: true,
+        decoded_genotype: false,
+        canonical_list: None,
+        priority_info_list: vec![],
+        priority_format_list: vec![],
+        info_list: vec![],
+        format_list: vec![],
+        replace_sample_name: None,
+        group_names: None,
+        annotation_fields: vec![],
+        annotation_transcript: AnnotationTranscript::MostSevere,
+        split_annotations: true,
+        samples: None,
+        missing_field: MissingFieldPolicy::Error,
+        genotype_filters: vec![],
+        inheritance: None,
+    };
+    let mut vcf_data_reader = BufReader::new(&vcf_data[..]);
+    let mut vcf_reader = vcf::VCFReader::new(&mut vcf_data_reader)?;
+    let header_contents = create_header_line(vcf_reader.header(), &config)?;
+    // The limit falls between the two annotation rows of the first variant
+    let mut writer = RowLimitWriter {
+        header: Vec::new(),
+        rows: 0,
+        max_rows: 2,
+    };
+    let rows = vcf2table(
+        &mut vcf_reader,
+        &header_contents,
+        &config,
+        None,
+        true,
+        &mut writer,
+    )?;
+    assert_eq!(rows, 1);
+    assert_eq!(writer.rows, 2);
+    Ok(())
+}
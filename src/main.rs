@@ -41,6 +41,8 @@ struct Cli {
         help = "Base path to search additional CSV/VCF/Image"
     )]
     base_path: Option<String>,
+    #[clap(long = "verbose", short = 'v', help = "Print inferred column types")]
+    verbose: bool,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -86,6 +88,7 @@ fn main() -> anyhow::Result<()> {
         &cli.output_filename,
         base_path,
         canonical_list,
+        cli.verbose,
     )?;
     Ok(())
 }
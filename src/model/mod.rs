@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use xlsxwriter::worksheet::{WorksheetCol, WorksheetRow};
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
@@ -11,6 +12,20 @@ pub enum CellValue {
     Boolean(bool),
     Url(String),
     Formula(String),
+    Rich(RichText),
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RichText {
+    pub rich: Vec<RichTextRun>,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RichTextRun {
+    pub text: String,
+    pub format: Option<FormatDef>,
 }
 
 impl From<String> for CellValue {
@@ -52,6 +67,23 @@ impl Default for CellType {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Deserialize, Serialize, Hash, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnchorPosition {
+    #[default]
+    Below,
+    Above,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub struct CellAnchorDef {
+    pub source: Option<usize>,
+    #[serde(default)]
+    pub position: AnchorPosition,
+}
+
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct CellDef {
@@ -63,10 +95,46 @@ pub struct CellDef {
     pub column: Option<WorksheetCol>,
     pub column_relative: Option<i32>,
     pub format: Option<FormatDef>,
-    pub comment: Option<String>,
+    pub comment: Option<Comment>,
     pub url: Option<String>,
+    pub link_to: Option<LinkToDef>,
     pub merge_row: Option<WorksheetRow>,
     pub merge_column: Option<WorksheetCol>,
+    pub anchor: Option<CellAnchorDef>,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LinkToDef {
+    pub sheet: String,
+    pub cell: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Comment {
+    Text(String),
+    Def(CommentDef),
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CommentDef {
+    pub text: String,
+    pub author: Option<String>,
+    pub visible: Option<bool>,
+    pub width: Option<u16>,
+    pub height: Option<u16>,
+    pub font_name: Option<String>,
+    pub font_size: Option<u16>,
+    pub background_color: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Deserialize, Serialize, Eq, Hash, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum FontScript {
+    Superscript,
+    Subscript,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Deserialize, Serialize, Eq, Hash, Ord)]
@@ -172,6 +240,11 @@ pub struct FormatDef {
     pub font_color: Option<String>,
     #[serde(default)]
     pub underline: bool,
+    #[serde(default)]
+    pub bold: bool,
+    #[serde(default)]
+    pub italic: bool,
+    pub font_script: Option<FontScript>,
     pub background_color: Option<String>,
     pub num_format: Option<String>,
     pub border: Option<BorderFormatDefChoice>,
@@ -183,6 +256,7 @@ pub enum SheetSourceType {
     CSV,
     TSV,
     VCF,
+    BCF,
 }
 
 impl Default for SheetSourceType {
@@ -200,8 +274,16 @@ pub struct SheetSourceColumnDef {
     #[serde(default)]
     pub header_type: CellType,
     pub header_value: Option<CellValue>,
-    pub header_comment: Option<String>,
+    pub header_comment: Option<Comment>,
+    pub comment_from: Option<String>,
     pub link_prefix: Option<String>,
+    pub link_template: Option<String>,
+    pub link_text: Option<String>,
+    pub width: Option<f64>,
+    #[serde(default)]
+    pub hidden: bool,
+    pub outline_level: Option<u8>,
+    pub column_format: Option<FormatDef>,
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Hash, Default)]
@@ -219,6 +301,133 @@ pub struct VCFConfigDef {
     pub priority_info: Option<Vec<String>>,
     #[serde(default)]
     pub priority_format: Option<Vec<String>>,
+    #[serde(default)]
+    pub group_genotypes: bool,
+    #[serde(default)]
+    pub collapse_genotypes: bool,
+    #[serde(default)]
+    pub annotation_fields: Option<Vec<String>>,
+    #[serde(default)]
+    pub annotation_transcript: AnnotationTranscript,
+    #[serde(default)]
+    pub split_annotations: bool,
+    #[serde(default)]
+    pub regions: Option<RegionsDef>,
+    #[serde(default)]
+    pub samples: Option<SamplesDef>,
+    #[serde(default)]
+    pub sample_names: Option<SampleNamesDef>,
+    #[serde(default)]
+    pub group_name: Option<String>,
+    #[serde(default)]
+    pub group_names: Option<BTreeMap<String, String>>,
+    #[serde(default)]
+    pub missing_field: MissingFieldPolicy,
+    #[serde(default)]
+    pub metadata_sheet: Option<String>,
+    #[serde(default)]
+    pub genotype_filters: Vec<GenotypeFilterDef>,
+    #[serde(default)]
+    pub pedigree: Option<String>,
+    #[serde(default)]
+    pub inheritance: Vec<InheritanceModel>,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Hash)]
+#[serde(untagged)]
+pub enum RegionsDef {
+    List(Vec<String>),
+    Bed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Hash)]
+#[serde(untagged)]
+pub enum SamplesDef {
+    List(Vec<String>),
+    Def(SampleSelectionDef),
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Hash)]
+#[serde(untagged)]
+pub enum SampleNamesDef {
+    Map(BTreeMap<String, String>),
+    File(String),
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Hash, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct SampleSelectionDef {
+    #[serde(default)]
+    pub include: Option<Vec<String>>,
+    #[serde(default)]
+    pub exclude: Option<Vec<String>>,
+    #[serde(default)]
+    pub pattern: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Deserialize, Serialize, Hash, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnnotationTranscript {
+    #[default]
+    MostSevere,
+    Canonical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Deserialize, Serialize, Hash, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum MissingFieldPolicy {
+    #[default]
+    Error,
+    Skip,
+    Empty,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub struct GenotypeFilterDef {
+    pub sample: String,
+    #[serde(default)]
+    pub genotype: Option<Vec<GenotypeClass>>,
+    #[serde(default)]
+    pub min_dp: Option<u32>,
+    #[serde(default)]
+    pub min_gq: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Deserialize, Serialize, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum GenotypeClass {
+    HomRef,
+    Het,
+    HomAlt,
+    Missing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Deserialize, Serialize, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum InheritanceModel {
+    DeNovo,
+    HomozygousRecessive,
+    Dominant,
+    CompoundHeterozygous,
+}
+
+impl InheritanceModel {
+    pub const ALL: [InheritanceModel; 4] = [
+        InheritanceModel::DeNovo,
+        InheritanceModel::HomozygousRecessive,
+        InheritanceModel::Dominant,
+        InheritanceModel::CompoundHeterozygous,
+    ];
+
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            InheritanceModel::DeNovo => "de-novo",
+            InheritanceModel::HomozygousRecessive => "homozygous-recessive",
+            InheritanceModel::Dominant => "dominant",
+            InheritanceModel::CompoundHeterozygous => "compound-heterozygous",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
@@ -255,10 +464,73 @@ pub struct TableFilterList {
     pub items: Vec<String>,
 }
 
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum SheetSourceFile {
+    Path(String),
+    List(Vec<String>),
+}
+
+impl SheetSourceFile {
+    pub fn patterns(&self) -> &[String] {
+        match self {
+            SheetSourceFile::Path(path) => std::slice::from_ref(path),
+            SheetSourceFile::List(list) => list,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Deserialize, Serialize, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum RelativePosition {
+    AfterPrevious,
+    SameAsPrevious,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Deserialize, Serialize, Hash)]
+#[serde(untagged)]
+pub enum SourcePosition<T> {
+    Absolute(T),
+    Relative(RelativePosition),
+}
+
+impl<T: Default> Default for SourcePosition<T> {
+    fn default() -> Self {
+        SourcePosition::Absolute(T::default())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Deserialize, Serialize, Hash, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowPolicy {
+    #[default]
+    Truncate,
+    NewSheet,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Deserialize, Serialize, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum SourceDelimiter {
+    Comma,
+    Tab,
+    Semicolon,
+    Pipe,
+    Auto,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SourceFileColumnDef {
+    #[serde(default = "source_file_column_header")]
+    pub header: String,
+    pub pattern: Option<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct SheetSourceDef {
-    pub file: Option<String>,
+    pub file: Option<SheetSourceFile>,
     pub data: Option<String>,
     #[serde(default)]
     pub format: SheetSourceType,
@@ -272,20 +544,42 @@ pub struct SheetSourceDef {
     #[serde(default = "true_value")]
     pub has_header: bool,
     #[serde(default)]
-    pub start_row: WorksheetRow,
+    pub start_row: SourcePosition<WorksheetRow>,
     #[serde(default)]
-    pub start_column: WorksheetCol,
+    pub start_column: SourcePosition<WorksheetCol>,
+    pub gap: Option<u16>,
     pub vcf_config: Option<VCFConfigDef>,
     pub comment_line_prefix: Option<String>,
     pub filter_list: Option<TableFilterList>,
+    pub source_file_column: Option<SourceFileColumnDef>,
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+    pub infer_types: Option<usize>,
+    pub encoding: Option<String>,
+    pub delimiter: Option<SourceDelimiter>,
+    pub quoting: Option<bool>,
+    pub skip_lines: Option<usize>,
+    pub skip_until: Option<String>,
+    pub header_rows: Option<usize>,
+    #[serde(default)]
+    pub merge_header_rows: bool,
+    pub max_rows: Option<usize>,
+    pub tail: Option<usize>,
+    pub auto_fit: Option<AutoFit>,
+    #[serde(default)]
+    pub row_groups: Vec<GroupDef>,
+    #[serde(default)]
+    pub column_groups: Vec<GroupDef>,
 }
 
 impl SheetSourceDef {
     pub fn suggest_format(&self) -> SheetSourceType {
         if self.format == SheetSourceType::Auto {
-            if let Some(file) = self.file.as_deref() {
+            if let Some(file) = self.file.as_ref().and_then(|x| x.patterns().first()) {
                 if file.ends_with(".vcf") || file.ends_with(".vcf.gz") {
                     SheetSourceType::VCF
+                } else if file.ends_with(".bcf") {
+                    SheetSourceType::BCF
                 } else if file.ends_with(".csv") || file.ends_with(".csv.gz") {
                     SheetSourceType::CSV
                 } else {
@@ -305,7 +599,7 @@ impl From<SheetSource> for Vec<SheetSourceDef> {
         match x {
             SheetSource::Def(def) => def,
             SheetSource::Path(path) => vec![SheetSourceDef {
-                file: Some(path),
+                file: Some(SheetSourceFile::Path(path)),
                 data: None,
                 format: SheetSourceType::Auto,
                 columns: None,
@@ -314,11 +608,27 @@ impl From<SheetSource> for Vec<SheetSourceDef> {
                 table_style_type: None,
                 table_style_type_num: None,
                 has_header: true,
-                start_row: 0,
-                start_column: 0,
+                start_row: SourcePosition::Absolute(0),
+                start_column: SourcePosition::Absolute(0),
+                gap: None,
                 vcf_config: None,
                 comment_line_prefix: None,
                 filter_list: None,
+                source_file_column: None,
+                overflow: OverflowPolicy::Truncate,
+                infer_types: None,
+                encoding: None,
+                delimiter: None,
+                quoting: None,
+                skip_lines: None,
+                skip_until: None,
+                header_rows: None,
+                merge_header_rows: false,
+                max_rows: None,
+                tail: None,
+                auto_fit: None,
+                row_groups: Vec::new(),
+                column_groups: Vec::new(),
             }],
         }
     }
@@ -346,6 +656,7 @@ pub struct SheetImage {
 #[serde(rename_all = "kebab-case")]
 pub struct WorksheetDef {
     pub name: Option<String>,
+    pub description: Option<String>,
     pub source: Option<SheetSource>,
     pub freeze: Option<SheetFreeze>,
     #[serde(default)]
@@ -356,16 +667,104 @@ pub struct WorksheetDef {
     pub row_heights: Vec<f64>,
     #[serde(default)]
     pub images: Vec<SheetImage>,
+    pub auto_fit: Option<AutoFit>,
+    #[serde(default)]
+    pub row_groups: Vec<GroupDef>,
+    #[serde(default)]
+    pub column_groups: Vec<GroupDef>,
+    pub outline: Option<OutlineDef>,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum GroupPosition {
+    Index(u32),
+    Name(String),
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct GroupDef {
+    pub first: Option<GroupPosition>,
+    pub last: Option<GroupPosition>,
+    #[serde(default = "default_outline_level")]
+    pub level: u8,
+    #[serde(default)]
+    pub collapsed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct OutlineDef {
+    #[serde(default = "true_value")]
+    pub summary_below: bool,
+    #[serde(default = "true_value")]
+    pub summary_right: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum AutoFit {
+    Enabled(bool),
+    Def(AutoFitDef),
+}
+
+impl AutoFit {
+    pub fn options(&self) -> Option<AutoFitDef> {
+        match self {
+            AutoFit::Enabled(true) => Some(AutoFitDef {
+                min_width: None,
+                max_width: None,
+                header: true,
+            }),
+            AutoFit::Enabled(false) => None,
+            AutoFit::Def(def) => Some(def.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AutoFitDef {
+    pub min_width: Option<f64>,
+    pub max_width: Option<f64>,
+    #[serde(default = "true_value")]
+    pub header: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct WorkbookDef {
     pub sheets: Vec<WorksheetDef>,
+    #[serde(default)]
+    pub constant_memory: bool,
+    pub tmpdir: Option<String>,
+    pub contents: Option<ContentsDef>,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ContentsDef {
+    #[serde(default = "contents_sheet_name")]
+    pub name: String,
+    pub back_link_cell: Option<String>,
 }
 
 fn true_value() -> bool {
     true
 }
 
+fn default_outline_level() -> u8 {
+    1
+}
+
+fn contents_sheet_name() -> String {
+    "Contents".to_string()
+}
+
+fn source_file_column_header() -> String {
+    "Source file".to_string()
+}
+
 #[cfg(test)]
 mod test;
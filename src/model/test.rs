@@ -10,26 +10,41 @@ fn test_json_load() -> anyhow::Result<()> {
         sheets: vec![
             WorksheetDef {
                 name: Some("Sheet 1 (CSV)".to_string()),
+                description: None,
                 source: Some(SheetSource::Def(vec![SheetSourceDef {
-                    file: Some("data/data1.csv".to_string()),
+                    file: Some(SheetSourceFile::Path("data/data1.csv".to_string())),
                     data: None,
                     format: SheetSourceType::Auto,
                     columns: Some(vec![
                         SheetSourceColumnDef {
                             format: None,
                             cell_type: CellType::Number,
-                            header_comment: Some("header comment".to_string()),
+                            header_comment: Some(Comment::Text("header comment".to_string())),
+                            comment_from: None,
                             header_type: CellType::Auto,
                             header_value: None,
                             link_prefix: None,
+                            link_template: None,
+                            link_text: None,
+                            width: None,
+                            hidden: false,
+                            outline_level: None,
+                            column_format: None,
                         },
                         SheetSourceColumnDef {
                             format: None,
                             cell_type: CellType::String,
                             header_comment: None,
+                            comment_from: None,
                             header_type: CellType::Auto,
                             header_value: None,
                             link_prefix: None,
+                            link_template: None,
+                            link_text: None,
+                            width: None,
+                            hidden: false,
+                            outline_level: None,
+                            column_format: None,
                         },
                         SheetSourceColumnDef {
                             format: Some(FormatDef {
@@ -40,28 +55,52 @@ fn test_json_load() -> anyhow::Result<()> {
                                 num_format: Some("0.00".to_string()),
                                 border: None,
                                 underline: false,
+                                bold: false,
+                                italic: false,
+                                font_script: None,
                             }),
                             cell_type: CellType::Number,
                             header_comment: None,
+                            comment_from: None,
                             header_type: CellType::Auto,
                             header_value: None,
                             link_prefix: None,
+                            link_template: None,
+                            link_text: None,
+                            width: None,
+                            hidden: false,
+                            outline_level: None,
+                            column_format: None,
                         },
                         SheetSourceColumnDef {
                             format: None,
                             cell_type: CellType::String,
                             header_comment: None,
+                            comment_from: None,
                             header_type: CellType::Auto,
                             header_value: None,
                             link_prefix: None,
+                            link_template: None,
+                            link_text: None,
+                            width: None,
+                            hidden: false,
+                            outline_level: None,
+                            column_format: None,
                         },
                         SheetSourceColumnDef {
                             format: None,
                             cell_type: CellType::String,
                             header_comment: None,
+                            comment_from: None,
                             header_type: CellType::Auto,
                             header_value: None,
                             link_prefix: None,
+                            link_template: None,
+                            link_text: None,
+                            width: None,
+                            hidden: false,
+                            outline_level: None,
+                            column_format: None,
                         },
                     ]),
                     autofilter: true,
@@ -69,14 +108,30 @@ fn test_json_load() -> anyhow::Result<()> {
                     table_style_type: Some(TableStyleType::Dark),
                     table_style_type_num: Some(2),
                     has_header: true,
-                    start_row: 1,
-                    start_column: 1,
+                    start_row: SourcePosition::Absolute(1),
+                    start_column: SourcePosition::Absolute(1),
+                    gap: None,
                     vcf_config: None,
                     comment_line_prefix: None,
                     filter_list: Some(TableFilterList {
                         column_header: "Header B".to_string(),
                         items: vec!["A".to_string(), "C".to_string()],
                     }),
+                    source_file_column: None,
+                    overflow: OverflowPolicy::Truncate,
+                    infer_types: None,
+                    encoding: None,
+                    delimiter: None,
+                    quoting: None,
+                    skip_lines: None,
+                    skip_until: None,
+                    header_rows: None,
+                    merge_header_rows: false,
+                    max_rows: None,
+                    tail: None,
+                    auto_fit: None,
+                    row_groups: vec![],
+                    column_groups: vec![],
                 }])),
                 freeze: None,
                 cells: vec![CellDef {
@@ -94,27 +149,42 @@ fn test_json_load() -> anyhow::Result<()> {
                         num_format: None,
                         border: Some(BorderFormatDefChoice::TypeOnly(BorderType::Medium)),
                         underline: false,
+                        bold: false,
+                        italic: false,
+                        font_script: None,
                     }),
-                    comment: Some("Title comment".to_string()),
+                    comment: Some(Comment::Text("Title comment".to_string())),
                     url: None,
+                    link_to: None,
                     merge_column: None,
                     merge_row: None,
+                    anchor: None,
                 }],
                 column_widths: vec![16., 10., 8.],
                 row_heights: vec![],
                 images: vec![],
+                auto_fit: None,
+                row_groups: vec![],
+                column_groups: vec![],
+                outline: None,
             },
             WorksheetDef {
                 name: Some("Sheet 1 (TSV)".to_string()),
+                description: None,
                 source: Some(SheetSource::Path("data/data1.txt".to_string())),
                 freeze: Some(SheetFreeze { row: 1, column: 1 }),
                 cells: vec![],
                 column_widths: vec![],
                 row_heights: vec![],
                 images: vec![],
+                auto_fit: None,
+                row_groups: vec![],
+                column_groups: vec![],
+                outline: None,
             },
             WorksheetDef {
                 name: Some("Cells".to_string()),
+                description: None,
                 source: None,
                 freeze: None,
                 cells: vec![
@@ -128,8 +198,10 @@ fn test_json_load() -> anyhow::Result<()> {
                         format: None,
                         comment: None,
                         url: None,
+                        link_to: None,
                         merge_column: None,
                         merge_row: None,
+                        anchor: None,
                     },
                     CellDef {
                         column: Some(1),
@@ -155,11 +227,16 @@ fn test_json_load() -> anyhow::Result<()> {
                                 },
                             ])),
                             underline: false,
+                            bold: false,
+                            italic: false,
+                            font_script: None,
                         }),
-                        comment: Some("Cell comment".to_string()),
+                        comment: Some(Comment::Text("Cell comment".to_string())),
                         url: None,
+                        link_to: None,
                         merge_column: None,
                         merge_row: None,
+                        anchor: None,
                     },
                     CellDef {
                         column: Some(2),
@@ -171,8 +248,10 @@ fn test_json_load() -> anyhow::Result<()> {
                         format: None,
                         comment: None,
                         url: None,
+                        link_to: None,
                         merge_column: None,
                         merge_row: None,
+                        anchor: None,
                     },
                     CellDef {
                         column: Some(3),
@@ -184,8 +263,10 @@ fn test_json_load() -> anyhow::Result<()> {
                         format: None,
                         comment: None,
                         url: None,
+                        link_to: None,
                         merge_column: None,
                         merge_row: None,
+                        anchor: None,
                     },
                     CellDef {
                         column: Some(4),
@@ -197,8 +278,10 @@ fn test_json_load() -> anyhow::Result<()> {
                         format: None,
                         comment: None,
                         url: None,
+                        link_to: None,
                         merge_column: None,
                         merge_row: None,
+                        anchor: None,
                     },
                     CellDef {
                         column: Some(0),
@@ -210,8 +293,10 @@ fn test_json_load() -> anyhow::Result<()> {
                         format: None,
                         comment: None,
                         url: None,
+                        link_to: None,
                         merge_column: Some(3),
                         merge_row: Some(2),
+                        anchor: None,
                     },
                     CellDef {
                         column: Some(0),
@@ -223,8 +308,10 @@ fn test_json_load() -> anyhow::Result<()> {
                         format: None,
                         comment: None,
                         url: None,
+                        link_to: None,
                         merge_column: None,
                         merge_row: None,
+                        anchor: None,
                     },
                     CellDef {
                         column: Some(1),
@@ -236,8 +323,10 @@ fn test_json_load() -> anyhow::Result<()> {
                         format: None,
                         comment: None,
                         url: None,
+                        link_to: None,
                         merge_column: None,
                         merge_row: None,
+                        anchor: None,
                     },
                     CellDef {
                         column: Some(2),
@@ -249,8 +338,10 @@ fn test_json_load() -> anyhow::Result<()> {
                         format: None,
                         comment: None,
                         url: Some("internal:'Kraken2'!A1".to_string()),
+                        link_to: None,
                         merge_column: None,
                         merge_row: None,
+                        anchor: None,
                     },
                 ],
                 column_widths: vec![],
@@ -271,20 +362,32 @@ fn test_json_load() -> anyhow::Result<()> {
                         height_scale: None,
                     },
                 ],
+                auto_fit: None,
+                row_groups: vec![],
+                column_groups: vec![],
+                outline: None,
             },
             WorksheetDef {
                 name: Some("VCF Import 1".to_string()),
+                description: None,
                 source: Some(SheetSource::Path("vcf/simple1-snpeff.vcf".to_string())),
                 freeze: None,
                 cells: vec![],
                 column_widths: vec![],
                 row_heights: vec![],
                 images: vec![],
+                auto_fit: None,
+                row_groups: vec![],
+                column_groups: vec![],
+                outline: None,
             },
             WorksheetDef {
                 name: Some("VCF Import 2".to_string()),
+                description: None,
                 source: Some(SheetSource::Def(vec![SheetSourceDef {
-                    file: Some("vcf/1kGP-subset-snpeff.vcf".to_string()),
+                    file: Some(SheetSourceFile::Path(
+                        "vcf/1kGP-subset-snpeff.vcf".to_string(),
+                    )),
                     data: None,
                     format: SheetSourceType::Auto,
                     vcf_config: Some(VCFConfigDef {
@@ -293,6 +396,21 @@ fn test_json_load() -> anyhow::Result<()> {
                         info: None,
                         format: None,
                         priority_format: None,
+                        group_genotypes: false,
+                        collapse_genotypes: false,
+                        annotation_fields: None,
+                        annotation_transcript: AnnotationTranscript::MostSevere,
+                        split_annotations: false,
+                        regions: None,
+                        samples: None,
+                        sample_names: None,
+                        group_name: None,
+                        group_names: None,
+                        missing_field: MissingFieldPolicy::Error,
+                        metadata_sheet: None,
+                        genotype_filters: vec![],
+                        pedigree: None,
+                        inheritance: vec![],
                         priority_info: None,
                     }),
                     columns: None,
@@ -301,22 +419,43 @@ fn test_json_load() -> anyhow::Result<()> {
                     table_style_type: None,
                     table_style_type_num: None,
                     has_header: true,
-                    start_row: 1,
-                    start_column: 1,
+                    start_row: SourcePosition::Absolute(1),
+                    start_column: SourcePosition::Absolute(1),
+                    gap: None,
                     comment_line_prefix: None,
                     filter_list: Some(TableFilterList {
                         column_header: "SnpEff Impact".to_string(),
                         items: vec!["LOW".to_string()],
                     }),
+                    source_file_column: None,
+                    overflow: OverflowPolicy::Truncate,
+                    infer_types: None,
+                    encoding: None,
+                    delimiter: None,
+                    quoting: None,
+                    skip_lines: None,
+                    skip_until: None,
+                    header_rows: None,
+                    merge_header_rows: false,
+                    max_rows: None,
+                    tail: None,
+                    auto_fit: None,
+                    row_groups: vec![],
+                    column_groups: vec![],
                 }])),
                 freeze: Some(SheetFreeze { row: 2, column: 4 }),
                 cells: vec![],
                 column_widths: vec![],
                 row_heights: vec![],
                 images: vec![],
+                auto_fit: None,
+                row_groups: vec![],
+                column_groups: vec![],
+                outline: None,
             },
             WorksheetDef {
                 name: None,
+                description: None,
                 source: Some(SheetSource::Def(vec![SheetSourceDef {
                     file: None,
                     data: Some("A\tB\nC\tD".to_string()),
@@ -328,19 +467,40 @@ fn test_json_load() -> anyhow::Result<()> {
                     table_style_type: Some(TableStyleType::Light),
                     table_style_type_num: Some(20),
                     has_header: true,
-                    start_row: 0,
-                    start_column: 0,
+                    start_row: SourcePosition::Absolute(0),
+                    start_column: SourcePosition::Absolute(0),
+                    gap: None,
                     comment_line_prefix: None,
                     filter_list: None,
+                    source_file_column: None,
+                    overflow: OverflowPolicy::Truncate,
+                    infer_types: None,
+                    encoding: None,
+                    delimiter: None,
+                    quoting: None,
+                    skip_lines: None,
+                    skip_until: None,
+                    header_rows: None,
+                    merge_header_rows: false,
+                    max_rows: None,
+                    tail: None,
+                    auto_fit: None,
+                    row_groups: vec![],
+                    column_groups: vec![],
                 }])),
                 freeze: None,
                 cells: vec![],
                 column_widths: vec![],
                 row_heights: vec![],
                 images: vec![],
+                auto_fit: None,
+                row_groups: vec![],
+                column_groups: vec![],
+                outline: None,
             },
             WorksheetDef {
                 name: Some("Relative Position".to_string()),
+                description: None,
                 source: None,
                 freeze: None,
                 cells: vec![
@@ -354,8 +514,10 @@ fn test_json_load() -> anyhow::Result<()> {
                         format: None,
                         comment: None,
                         url: None,
+                        link_to: None,
                         merge_row: None,
                         merge_column: None,
+                        anchor: None,
                     },
                     CellDef {
                         value: Some(CellValue::String("A".to_string())),
@@ -367,8 +529,10 @@ fn test_json_load() -> anyhow::Result<()> {
                         format: None,
                         comment: None,
                         url: None,
+                        link_to: None,
                         merge_row: None,
                         merge_column: None,
+                        anchor: None,
                     },
                     CellDef {
                         value: Some(CellValue::String("B".to_string())),
@@ -380,8 +544,10 @@ fn test_json_load() -> anyhow::Result<()> {
                         format: None,
                         comment: None,
                         url: None,
+                        link_to: None,
                         merge_row: None,
                         merge_column: None,
+                        anchor: None,
                     },
                     CellDef {
                         value: Some(CellValue::String("next line".to_string())),
@@ -393,8 +559,10 @@ fn test_json_load() -> anyhow::Result<()> {
                         format: None,
                         comment: None,
                         url: None,
+                        link_to: None,
                         merge_row: None,
                         merge_column: None,
+                        anchor: None,
                     },
                     CellDef {
                         value: Some(CellValue::String("C".to_string())),
@@ -406,8 +574,10 @@ fn test_json_load() -> anyhow::Result<()> {
                         format: None,
                         comment: None,
                         url: None,
+                        link_to: None,
                         merge_row: None,
                         merge_column: None,
+                        anchor: None,
                     },
                     CellDef {
                         value: Some(CellValue::String("D".to_string())),
@@ -419,8 +589,10 @@ fn test_json_load() -> anyhow::Result<()> {
                         format: None,
                         comment: None,
                         url: None,
+                        link_to: None,
                         merge_row: None,
                         merge_column: None,
+                        anchor: None,
                     },
                     CellDef {
                         value: Some(CellValue::String("E".to_string())),
@@ -432,18 +604,138 @@ fn test_json_load() -> anyhow::Result<()> {
                         format: None,
                         comment: None,
                         url: None,
+                        link_to: None,
                         merge_row: None,
                         merge_column: None,
+                        anchor: None,
                     },
                 ],
                 column_widths: vec![],
                 row_heights: vec![],
                 images: vec![],
+                auto_fit: None,
+                row_groups: vec![],
+                column_groups: vec![],
+                outline: None,
             },
         ],
+        constant_memory: false,
+        tmpdir: None,
+        contents: None,
     };
 
     assert_eq!(expected, data);
 
     Ok(())
 }
+
+#[test]
+fn test_rich_text_load() -> anyhow::Result<()> {
+    let value: CellValue = serde_json::from_str(
+        r#"{"rich": [{"text": "Gene "}, {"text": "TP53", "format": {"bold": true, "font-color": "red"}}, {"text": "2", "format": {"font-script": "superscript"}}]}"#,
+    )?;
+    assert_eq!(
+        value,
+        CellValue::Rich(RichText {
+            rich: vec![
+                RichTextRun {
+                    text: "Gene ".to_string(),
+                    format: None,
+                },
+                RichTextRun {
+                    text: "TP53".to_string(),
+                    format: Some(FormatDef {
+                        font_name: None,
+                        font_size: None,
+                        font_color: Some("red".to_string()),
+                        underline: false,
+                        bold: true,
+                        italic: false,
+                        font_script: None,
+                        background_color: None,
+                        num_format: None,
+                        border: None,
+                    }),
+                },
+                RichTextRun {
+                    text: "2".to_string(),
+                    format: Some(FormatDef {
+                        font_name: None,
+                        font_size: None,
+                        font_color: None,
+                        underline: false,
+                        bold: false,
+                        italic: false,
+                        font_script: Some(FontScript::Superscript),
+                        background_color: None,
+                        num_format: None,
+                        border: None,
+                    }),
+                },
+            ],
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn test_vcf_subset_load() -> anyhow::Result<()> {
+    let config: VCFConfigDef = serde_json::from_str(
+        r#"{"regions": ["13:32900000-32910000", "17"], "samples": ["HG00099", "HG00100"]}"#,
+    )?;
+    assert_eq!(
+        config.regions,
+        Some(RegionsDef::List(vec![
+            "13:32900000-32910000".to_string(),
+            "17".to_string()
+        ]))
+    );
+    assert_eq!(
+        config.samples,
+        Some(SamplesDef::List(vec![
+            "HG00099".to_string(),
+            "HG00100".to_string()
+        ]))
+    );
+
+    let config: VCFConfigDef = serde_json::from_str(
+        r#"{"regions": "panel.bed", "samples": {"exclude": ["HG00099"], "pattern": "^SRP"}}"#,
+    )?;
+    assert_eq!(
+        config.regions,
+        Some(RegionsDef::Bed("panel.bed".to_string()))
+    );
+    assert_eq!(
+        config.samples,
+        Some(SamplesDef::Def(SampleSelectionDef {
+            include: None,
+            exclude: Some(vec!["HG00099".to_string()]),
+            pattern: Some("^SRP".to_string()),
+        }))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_vcf_sample_names_load() -> anyhow::Result<()> {
+    let config: VCFConfigDef = serde_json::from_str(
+        r#"{"sample-names": {"HG00099": "Patient A"}, "group-name": "Cohort 1"}"#,
+    )?;
+    assert_eq!(
+        config.sample_names,
+        Some(SampleNamesDef::Map(
+            [("HG00099".to_string(), "Patient A".to_string())]
+                .into_iter()
+                .collect()
+        ))
+    );
+    assert_eq!(config.group_name, Some("Cohort 1".to_string()));
+
+    let config: VCFConfigDef = serde_json::from_str(r#"{"sample-names": "aliases.tsv"}"#)?;
+    assert_eq!(
+        config.sample_names,
+        Some(SampleNamesDef::File("aliases.tsv".to_string()))
+    );
+    assert_eq!(config.group_name, None);
+    Ok(())
+}